@@ -0,0 +1,44 @@
+// cargo build --release --manifest-path ../screenshot_rust/Cargo.toml
+// node screenshot-protocol.mjs https://example.com shot.png
+import { spawn } from 'node:child_process'
+import { writeFile } from 'node:fs/promises'
+
+const url = process.argv[2] || 'https://example.com'
+const output = process.argv[3] || 'shot.png'
+const bin = process.env.SCREENSHOT_BIN || new URL('../screenshot_rust/target/release/screenshot_rust', import.meta.url).pathname
+
+// the frame written by `--protocol-fd`, see screenshot_rust/src/protocol.rs
+const MAGIC = 'SSRP'
+const VERSION = 1
+
+const parseFrame = (frame) => {
+  if (frame.length < 13 || frame.subarray(0, 4).toString('latin1') !== MAGIC) {
+    throw new Error('Not a screenshot frame')
+  }
+  if (frame[4] !== VERSION) {
+    throw new Error(`Unsupported frame version ${frame[4]}`)
+  }
+
+  const imageLength = Number(frame.readBigUInt64BE(5))
+  const imageEnd = 13 + imageLength
+  const trailerLength = frame.readUInt32BE(imageEnd)
+  const trailer = frame.subarray(imageEnd + 4, imageEnd + 4 + trailerLength)
+
+  return { image: frame.subarray(13, imageEnd), result: JSON.parse(trailer.toString('utf8')) }
+}
+
+// fd 3 carries the frame, stdout and stderr stay free for logs
+const child = spawn(bin, ['--url', url, '--protocol-fd', '3'], { stdio: ['ignore', 'inherit', 'inherit', 'pipe'] })
+
+const chunks = []
+child.stdio[3].on('data', (chunk) => chunks.push(chunk))
+
+const code = await new Promise((resolve) => child.on('close', resolve))
+const { image, result } = parseFrame(Buffer.concat(chunks))
+
+if (result.success) {
+  await writeFile(output, image)
+  console.log(`Saved ${image.length} bytes of ${result.url} to ${output}`)
+} else {
+  console.log(`Capture failed (exit ${code}): ${result.error}`)
+}
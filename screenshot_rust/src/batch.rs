@@ -0,0 +1,520 @@
+use crate::{
+    launch_browser, output_extension, save_screenshot, take_screenshot, write_placeholder, Args, ScreenshotResult,
+};
+use chromiumoxide::Browser;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A single capture in the run manifest.
+#[derive(Serialize)]
+struct ManifestEntry {
+    request_id: String,
+    url: String,
+    /// the normalized url duplicates were detected by.
+    dedup_key: String,
+    file: Option<String>,
+    width: u32,
+    height: u32,
+    size: usize,
+    status: &'static str,
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wait_jitter_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_hash: Option<String>,
+}
+
+impl ManifestEntry {
+    /// The entry of a capture whose image is `dimensions` pixels, the viewport when it has none.
+    fn new(result: &ScreenshotResult, dedup_key: &str, dimensions: Option<(u32, u32)>) -> Self {
+        let (width, height) = dimensions.unwrap_or((result.width, result.height));
+
+        ManifestEntry {
+            request_id: result.request_id.clone(),
+            url: result.url.clone(),
+            dedup_key: dedup_key.to_string(),
+            file: result.file_path.clone(),
+            width,
+            height,
+            size: result.size,
+            status: if result.success { "success" } else { "failed" },
+            error: result.error.clone(),
+            wait_jitter_ms: result.wait_jitter_ms,
+            content_hash: result.content_hash.clone(),
+        }
+    }
+}
+
+/// The index of a whole batch run.
+#[derive(Serialize)]
+struct Manifest {
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    /// urls left out because an earlier url had the same dedup key.
+    duplicates: usize,
+    /// urls never started because the run stopped early.
+    skipped: usize,
+    time_budget_exceeded: bool,
+    /// captures finished before the failure that aborted the run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    completed_before_abort: Option<usize>,
+    /// browsers launched to replace a crashed one, with --retry-fresh-browser.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    browser_relaunches: Option<Vec<BrowserRelaunch>>,
+    /// every change of the adaptive concurrency limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    concurrency_timeline: Option<Vec<ConcurrencyChange>>,
+    entries: Vec<ManifestEntry>,
+}
+
+/// A change of the number of captures run at once.
+#[derive(Serialize)]
+struct ConcurrencyChange {
+    /// milliseconds since the run started.
+    at_ms: u64,
+    /// captures finished when the limit changed.
+    completed: usize,
+    concurrency: usize,
+    /// `start`, `increase` or `decrease`.
+    reason: &'static str,
+}
+
+/// A fresh browser launched after the previous one crashed.
+#[derive(Serialize)]
+struct BrowserRelaunch {
+    /// milliseconds since the run started.
+    at_ms: u64,
+    /// captures that failed with the crashed browser and were retried on this one.
+    retried: usize,
+}
+
+/// Errors of page operations once the browser process or its connection is gone.
+fn is_browser_crash(error: &str) -> bool {
+    let error = error.to_lowercase();
+    ["receiver is gone", "channel closed", "connection closed", "target closed", "browser closed"]
+        .iter()
+        .any(|symptom| error.contains(symptom))
+}
+
+/// A capture slower than this counts as struggling, like a failure.
+const SLOW_CAPTURE: Duration = Duration::from_secs(20);
+
+/// The number of captures run at once, adapted AIMD-style with `--concurrency-adaptive`: one more
+/// after a full round of quick successes, halved on a failure or a slow capture.
+struct Concurrency {
+    limit: usize,
+    adaptive: bool,
+    min: usize,
+    max: usize,
+    /// quick successes since the limit last changed.
+    successes: usize,
+    timeline: Vec<ConcurrencyChange>,
+}
+
+impl Concurrency {
+    fn new(args: &Args) -> Self {
+        let (min, max) = (args.min_concurrency, args.max_concurrency.max(args.min_concurrency));
+        let limit = if args.concurrency_adaptive {
+            args.concurrency.clamp(min, max)
+        } else {
+            args.concurrency
+        };
+
+        Concurrency {
+            limit,
+            adaptive: args.concurrency_adaptive,
+            min,
+            max,
+            successes: 0,
+            timeline: vec![ConcurrencyChange {
+                at_ms: 0,
+                completed: 0,
+                concurrency: limit,
+                reason: "start",
+            }],
+        }
+    }
+
+    fn record(&mut self, success: bool, elapsed: Duration, completed: usize, since_start: Duration) {
+        if !self.adaptive {
+            return;
+        }
+
+        let (limit, reason) = if !success || elapsed > SLOW_CAPTURE {
+            self.successes = 0;
+            ((self.limit / 2).max(self.min), "decrease")
+        } else {
+            self.successes += 1;
+            if self.successes < self.limit {
+                return;
+            }
+            self.successes = 0;
+            ((self.limit + 1).min(self.max), "increase")
+        };
+
+        if limit != self.limit {
+            self.limit = limit;
+            self.timeline.push(ConcurrencyChange {
+                at_ms: since_start.as_millis() as u64,
+                completed,
+                concurrency: limit,
+                reason,
+            });
+        }
+    }
+}
+
+/// Capture `url` as the `index`th job of the run on the `generation`th browser, returning its
+/// result, the dimensions of the image written and how long it took.
+async fn capture(
+    browser: Arc<Browser>,
+    generation: usize,
+    args: &Args,
+    output_dir: &Path,
+    index: usize,
+    url: &str,
+) -> (usize, usize, ScreenshotResult, Option<(u32, u32)>, Duration) {
+    let started = Instant::now();
+    let mut job = args.clone();
+    job.url = Some(url.to_string());
+    // every capture gets its own id, numbered from the run id when one was given
+    job.request_id = Some(format!("{}-{}", args.request_id(), index + 1));
+    job.output = output_dir
+        .join(file_name(index, url, &args.format))
+        .to_string_lossy()
+        .into_owned();
+
+    let mut result = ScreenshotResult::new(&job);
+    let mut dimensions = None;
+    match take_screenshot(&browser, &job, &mut result).await {
+        Ok(data) => {
+            // read from the header, a full-page capture is taller than the viewport
+            dimensions = image::io::Reader::new(std::io::Cursor::new(&data))
+                .with_guessed_format()
+                .ok()
+                .and_then(|reader| reader.into_dimensions().ok());
+            save_screenshot(&job, &data, &mut result);
+        }
+        Err(e) => {
+            result.error = Some(e);
+            write_placeholder(&job, &mut result);
+        }
+    }
+
+    (index, generation, result, dimensions, started.elapsed())
+}
+
+/// Capture every URL of `args.urls_file` into `args.output_dir`, returning whether all succeeded.
+///
+/// Up to `--concurrency` captures share the browser at once. Each finished capture is appended to
+/// `manifest.jsonl` straight away so an interrupted run stays usable, and `manifest.json` is
+/// written atomically once the run completes.
+pub async fn run(args: &Args) -> Result<bool, Box<dyn std::error::Error>> {
+    let listed = read_urls(args.urls_file.as_deref().unwrap_or_default())?;
+    let urls = dedup(&listed, args.strip_query, args.strip_fragment);
+    let output_dir = Path::new(&args.output_dir);
+
+    fs::create_dir_all(output_dir)?;
+
+    let mut journal = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(output_dir.join("manifest.jsonl"))?;
+
+    let started = Instant::now();
+    // a client certificate is presented to the origins of the listed urls
+    let targets = urls.iter().map(|(url, _)| url.as_str()).collect::<Vec<&str>>();
+    let (browser, cert_home) = launch_browser(args, &targets).await?;
+    let mut browser = Arc::new(browser);
+    // every relaunched browser has its own, all go once the last browser has exited
+    let mut cert_homes = vec![cert_home];
+    if let Some(wait) = args.pre_navigate_wait {
+        tokio::time::sleep(wait).await;
+    }
+    let mut entries = Vec::with_capacity(urls.len());
+    let mut time_budget_exceeded = false;
+    let mut completed_before_abort = None;
+    let mut concurrency = Concurrency::new(args);
+    let mut relaunches: Vec<BrowserRelaunch> = Vec::new();
+
+    {
+        let mut pending = urls.iter().enumerate();
+        let mut in_flight = FuturesUnordered::new();
+        let mut stopped = false;
+        let mut retried = std::collections::HashSet::new();
+
+        loop {
+            while !stopped && in_flight.len() < concurrency.limit {
+                // Only check before starting a capture so the ones in flight always finish
+                if args.max_runtime.is_some_and(|budget| started.elapsed() >= budget) {
+                    time_budget_exceeded = true;
+                    stopped = true;
+                    break;
+                }
+
+                match pending.next() {
+                    Some((index, (url, _))) => {
+                        in_flight.push(capture(browser.clone(), relaunches.len(), args, output_dir, index, url))
+                    }
+                    None => break,
+                }
+            }
+
+            let Some((index, generation, result, dimensions, elapsed)) = in_flight.next().await else {
+                break;
+            };
+
+            // A crash fails every capture in flight on that browser, only the first one relaunches it
+            if args.retry_fresh_browser
+                && result.error.as_deref().is_some_and(is_browser_crash)
+                && retried.insert(index)
+            {
+                if generation == relaunches.len() {
+                    let (relaunched, cert_home) = launch_browser(args, &targets).await?;
+                    browser = Arc::new(relaunched);
+                    cert_homes.push(cert_home);
+                    relaunches.push(BrowserRelaunch {
+                        at_ms: started.elapsed().as_millis() as u64,
+                        retried: 0,
+                    });
+                }
+                relaunches[generation].retried += 1;
+                in_flight.push(capture(browser.clone(), relaunches.len(), args, output_dir, index, &urls[index].0));
+                continue;
+            }
+
+            let entry = ManifestEntry::new(&result, &urls[index].1, dimensions);
+            serde_json::to_writer(&mut journal, &entry)?;
+            journal.write_all(b"\n")?;
+            journal.flush()?;
+            entries.push((index, entry));
+
+            concurrency.record(result.success, elapsed, entries.len(), started.elapsed());
+
+            if args.abort_on_first_failure && !result.success && !stopped {
+                completed_before_abort = Some(entries.len() - 1);
+                stopped = true;
+            }
+        }
+    }
+
+    // captures finish out of order, list them in the order of the urls file
+    entries.sort_by_key(|(index, _)| *index);
+    let entries = entries.into_iter().map(|(_, entry)| entry).collect::<Vec<ManifestEntry>>();
+
+    if let Ok(mut browser) = Arc::try_unwrap(browser) {
+        let _ = browser.close().await;
+        let _ = browser.wait().await;
+    }
+    drop(cert_homes);
+
+    let succeeded = entries.iter().filter(|entry| entry.error.is_none()).count();
+    let manifest = Manifest {
+        total: entries.len(),
+        succeeded,
+        failed: entries.len() - succeeded,
+        duplicates: listed.len() - urls.len(),
+        skipped: urls.len() - entries.len(),
+        time_budget_exceeded,
+        completed_before_abort,
+        browser_relaunches: args.retry_fresh_browser.then_some(relaunches),
+        concurrency_timeline: args.concurrency_adaptive.then_some(concurrency.timeline),
+        entries,
+    };
+
+    let json_manifest = serde_json::to_string_pretty(&manifest)?;
+    write_atomically(&output_dir.join("manifest.json"), json_manifest.as_bytes())?;
+    if args.output_manifest_csv {
+        write_atomically(&output_dir.join("manifest.csv"), &csv_manifest(&manifest)?)?;
+    }
+    if !args.quiet {
+        println!("{}", json_manifest);
+    } else {
+        for entry in manifest.entries.iter().filter(|entry| entry.error.is_some()) {
+            eprintln!(
+                "[{}] {}: {}",
+                entry.request_id,
+                entry.url,
+                entry.error.as_deref().unwrap_or_default()
+            );
+        }
+    }
+
+    Ok(manifest.failed == 0 && manifest.skipped == 0)
+}
+
+/// Read the URLs to capture, skipping blank lines and `#` comments.
+fn read_urls(path: &str) -> std::io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Drop urls whose dedup key was already seen, keeping the first one, paired with their key.
+fn dedup(urls: &[String], strip_query: bool, strip_fragment: bool) -> Vec<(String, String)> {
+    let mut seen = std::collections::HashSet::new();
+
+    urls.iter()
+        .map(|url| (url.clone(), dedup_key(url, strip_query, strip_fragment)))
+        .filter(|(_, key)| seen.insert(key.clone()))
+        .collect()
+}
+
+/// The url as parsed, so equivalent spellings compare equal, without the stripped parts.
+fn dedup_key(url: &str, strip_query: bool, strip_fragment: bool) -> String {
+    match url::Url::parse(url) {
+        Ok(mut parsed) => {
+            if strip_query {
+                parsed.set_query(None);
+            }
+            if strip_fragment {
+                parsed.set_fragment(None);
+            }
+            parsed.to_string()
+        }
+        // unparseable urls still get captured, and fail there with a proper error
+        Err(_) => url.to_string(),
+    }
+}
+
+/// A stable, filesystem safe file name for the capture at `index`.
+fn file_name(index: usize, url: &str, format: &str) -> String {
+    let stem: String = url
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(url)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .take(80)
+        .collect();
+    format!("{:04}-{}.{}", index + 1, stem.trim_matches('_'), output_extension(format))
+}
+
+/// One row per capture: url, status, file, width, height, bytes and error.
+fn csv_manifest(manifest: &Manifest) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["url", "status", "file", "width", "height", "bytes", "error"])?;
+
+    for entry in &manifest.entries {
+        writer.write_record([
+            entry.url.as_str(),
+            entry.status,
+            entry.file.as_deref().unwrap_or_default(),
+            &entry.width.to_string(),
+            &entry.height.to_string(),
+            &entry.size.to_string(),
+            entry.error.as_deref().unwrap_or_default(),
+        ])?;
+    }
+
+    Ok(writer.into_inner().map_err(|e| e.into_error())?)
+}
+
+/// Write through a temporary sibling and rename so readers never see a partial file.
+fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedup_key_normalizes_the_url() {
+        assert_eq!(dedup_key("HTTPS://Example.COM", false, false), "https://example.com/");
+        assert_eq!(dedup_key("https://example.com:443/a/../b", false, false), "https://example.com/b");
+        assert_eq!(dedup_key("not a url", false, false), "not a url");
+    }
+
+    #[test]
+    fn dedup_key_strips_query_and_fragment_on_request() {
+        let url = "https://example.com/page?utm=1#top";
+        assert_eq!(dedup_key(url, false, false), url);
+        assert_eq!(dedup_key(url, true, false), "https://example.com/page#top");
+        assert_eq!(dedup_key(url, false, true), "https://example.com/page?utm=1");
+        assert_eq!(dedup_key(url, true, true), "https://example.com/page");
+    }
+
+    #[test]
+    fn dedup_keeps_the_first_of_each_key() {
+        let urls = ["https://example.com/?a", "https://example.com/?b", "https://example.com/c"]
+            .map(String::from);
+        let kept = dedup(&urls, true, false);
+        assert_eq!(
+            kept.iter().map(|(url, _)| url.as_str()).collect::<Vec<&str>>(),
+            ["https://example.com/?a", "https://example.com/c"]
+        );
+    }
+
+    fn adaptive(limit: usize, min: usize, max: usize) -> Concurrency {
+        Concurrency {
+            limit,
+            adaptive: true,
+            min,
+            max,
+            successes: 0,
+            timeline: Vec::new(),
+        }
+    }
+
+    const QUICK: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn concurrency_grows_by_one_after_a_round_of_quick_successes() {
+        let mut concurrency = adaptive(2, 1, 4);
+        concurrency.record(true, QUICK, 1, QUICK);
+        assert_eq!(concurrency.limit, 2);
+        concurrency.record(true, QUICK, 2, QUICK);
+        assert_eq!(concurrency.limit, 3);
+
+        for completed in 3..6 {
+            concurrency.record(true, QUICK, completed, QUICK);
+        }
+        assert_eq!(concurrency.limit, 4);
+        assert_eq!(concurrency.timeline.iter().map(|change| change.reason).collect::<Vec<_>>(), ["increase"; 2]);
+    }
+
+    #[test]
+    fn concurrency_halves_on_a_failure_or_a_slow_capture() {
+        let mut concurrency = adaptive(8, 1, 8);
+        concurrency.record(false, QUICK, 1, QUICK);
+        assert_eq!(concurrency.limit, 4);
+        concurrency.record(true, SLOW_CAPTURE + QUICK, 2, QUICK);
+        assert_eq!(concurrency.limit, 2);
+    }
+
+    #[test]
+    fn concurrency_stays_within_its_bounds() {
+        let mut concurrency = adaptive(3, 2, 3);
+        concurrency.record(false, QUICK, 1, QUICK);
+        concurrency.record(false, QUICK, 2, QUICK);
+        assert_eq!(concurrency.limit, 2);
+
+        for completed in 3..10 {
+            concurrency.record(true, QUICK, completed, QUICK);
+        }
+        assert_eq!(concurrency.limit, 3);
+    }
+
+    #[test]
+    fn fixed_concurrency_never_changes() {
+        let mut concurrency = adaptive(4, 1, 8);
+        concurrency.adaptive = false;
+        concurrency.record(false, QUICK, 1, QUICK);
+        assert_eq!(concurrency.limit, 4);
+        assert!(concurrency.timeline.is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+// `--before-after-gif`: capture, interact, capture again and toggle between both in a GIF.
+
+use crate::{capture_current, postprocess, Args, ScreenshotResult};
+use chromiumoxide::Page;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, Rgba, RgbaImage};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+
+/// How long each state shows before the GIF toggles.
+const FRAME_MS: u32 = 1000;
+
+/// The before/after GIF and how much changed between the two states.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct BeforeAfter {
+    pub file_path: String,
+    pub before: FrameSize,
+    pub after: FrameSize,
+    /// share of pixels that differ, in percent of the larger frame.
+    pub changed_percent: f64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct FrameSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Capture the page, click every `--click` selector in order, capture again and write the GIF.
+///
+/// The "after" capture is returned.
+pub async fn run(page: &Page, args: &Args, path: &str, result: &mut ScreenshotResult) -> Result<Vec<u8>, String> {
+    let before = capture_current(page, args, result).await?;
+
+    for selector in &args.click {
+        page.find_element(selector.as_str())
+            .await
+            .map_err(|e| format!("Selector '{}' matched no element: {}", selector, e))?
+            .click()
+            .await
+            .map_err(|e| format!("Failed to click '{}': {}", selector, e))?;
+    }
+    tokio::time::sleep(args.delay).await;
+
+    let after = capture_current(page, args, result).await?;
+
+    postprocess::check_decode_budget(args, &before, result)?;
+    postprocess::check_decode_budget(args, &after, result)?;
+    let before_image = decode(&before)?;
+    let after_image = decode(&after)?;
+    let (width, height) = (
+        before_image.width().max(after_image.width()),
+        before_image.height().max(after_image.height()),
+    );
+    let before_frame = pad(&before_image, width, height);
+    let after_frame = pad(&after_image, width, height);
+
+    let changed = before_frame
+        .pixels()
+        .zip(after_frame.pixels())
+        .filter(|(before, after)| before != after)
+        .count();
+
+    write_gif(path, vec![before_frame, after_frame])?;
+
+    result.before_after = Some(BeforeAfter {
+        file_path: path.to_string(),
+        before: FrameSize {
+            width: before_image.width(),
+            height: before_image.height(),
+        },
+        after: FrameSize {
+            width: after_image.width(),
+            height: after_image.height(),
+        },
+        changed_percent: changed as f64 * 100.0 / (width as f64 * height as f64).max(1.0),
+    });
+
+    Ok(after)
+}
+
+fn decode(data: &[u8]) -> Result<RgbaImage, String> {
+    Ok(image::load_from_memory(data)
+        .map_err(|e| format!("Failed to decode screenshot: {}", e))?
+        .to_rgba8())
+}
+
+/// Place `image` at the top left of a white `width` x `height` canvas.
+fn pad(image: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    if image.dimensions() == (width, height) {
+        return image.clone();
+    }
+
+    let mut canvas = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+    image::imageops::replace(&mut canvas, image, 0, 0);
+    canvas
+}
+
+fn write_gif(path: &str, frames: Vec<RgbaImage>) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create GIF: {}", e))?;
+    let mut encoder = GifEncoder::new(file);
+
+    encoder
+        .set_repeat(Repeat::Infinite)
+        .map_err(|e| format!("Failed to encode GIF: {}", e))?;
+    encoder
+        .encode_frames(
+            frames
+                .into_iter()
+                .map(|frame| Frame::from_parts(frame, 0, 0, Delay::from_numer_denom_ms(FRAME_MS, 1))),
+        )
+        .map_err(|e| format!("Failed to encode GIF: {}", e))
+}
@@ -0,0 +1,53 @@
+// `--cas-dir`: a content-addressed store, each image is named by the SHA-256 of its bytes so identical
+// captures are stored once. `index.jsonl` maps every captured URL to its hash.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// A line of `index.jsonl`.
+#[derive(Serialize)]
+struct IndexEntry<'a> {
+    url: &'a str,
+    sha256: &'a str,
+    file: &'a str,
+}
+
+/// Store `data` under its hash unless an identical capture is already there and record `url` in the
+/// index, returning the hash, the file path and whether the bytes were already stored.
+pub fn store(dir: &str, url: &str, data: &[u8], extension: &str) -> Result<(String, String, bool), String> {
+    let hash = format!("{:x}", Sha256::digest(data));
+    let path = Path::new(dir).join(format!("{}.{}", hash, extension));
+    let file = path.to_string_lossy().into_owned();
+
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create content store: {}", e))?;
+
+    let existed = path.exists();
+    if !existed {
+        // through a temporary file, a crashed write must never sit under a valid hash
+        let tmp = Path::new(dir).join(format!("{}.{}.tmp", hash, uuid::Uuid::new_v4()));
+        fs::write(&tmp, data)
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| format!("Failed to save screenshot to content store: {}", e))?;
+    }
+
+    let mut line = serde_json::to_vec(&IndexEntry {
+        url,
+        sha256: &hash,
+        file: &file,
+    })
+    .map_err(|e| e.to_string())?;
+    line.push(b'\n');
+
+    // one write per line so concurrent batch captures append whole lines
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(Path::new(dir).join("index.jsonl"))
+        .and_then(|mut index| index.write_all(&line))
+        .map_err(|e| format!("Failed to update content store index: {}", e))?;
+
+    Ok((hash, file, existed))
+}
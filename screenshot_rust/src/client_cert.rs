@@ -0,0 +1,284 @@
+// Client certificates for mutual TLS. Chrome on Linux takes them from the NSS database under
+// `$HOME/.pki/nssdb`, so the certificate and key are imported into a private database and the
+// browser is launched with `HOME` pointing at it. Chrome only presents a certificate without
+// asking when the `auto_select_certificate` content setting says so, which is written into the
+// preferences of a user data directory inside that home, for the target origins only.
+
+use crate::network::{DocumentResponse, NetworkLog};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Whether the client certificate got the page through the TLS handshake.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ClientCertReport {
+    pub cert_file: String,
+    /// the main document was served, so the server accepted the handshake.
+    pub presented: bool,
+    pub tls_errors: Vec<String>,
+}
+
+/// The private home directory Chrome is launched with, removed when dropped, so keep it until
+/// the browser has exited.
+pub struct CertHome {
+    pub path: PathBuf,
+}
+
+impl CertHome {
+    /// The user data directory holding the preferences that select the certificate.
+    pub fn user_data_dir(&self) -> PathBuf {
+        self.path.join("chrome")
+    }
+}
+
+impl Drop for CertHome {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Import the PEM certificate and key into a fresh NSS database and let Chrome select it for the
+/// origins of `urls`, returning the home directory to launch Chrome with.
+///
+/// Everything is only readable by the current user. Other origins asking for a certificate, e.g.
+/// after a cross-origin redirect, get none.
+#[cfg(unix)]
+pub fn prepare(cert: &str, key: &str, urls: &[&str]) -> Result<CertHome, String> {
+    use std::io::Write;
+    use std::os::unix::fs::DirBuilderExt;
+
+    let home = CertHome {
+        path: std::env::temp_dir().join(format!("screenshot-client-cert-{}", uuid::Uuid::new_v4())),
+    };
+    let nssdb = home.path.join(".pki").join("nssdb");
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&nssdb)
+        .map_err(|e| format!("Failed to create certificate database: {}", e))?;
+
+    // the unencrypted bundle goes whether or not the import worked
+    let bundle = home.path.join("client.p12");
+    let imported = import(cert, key, &bundle, &nssdb);
+    let _ = fs::remove_file(&bundle);
+    imported?;
+
+    let profile = home.user_data_dir().join("Default");
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&profile)
+        .map_err(|e| format!("Failed to create browser profile: {}", e))?;
+    create_private(&profile.join("Preferences"))
+        .and_then(|mut file| file.write_all(preferences(urls).to_string().as_bytes()))
+        .map_err(|e| format!("Failed to write browser preferences: {}", e))?;
+
+    Ok(home)
+}
+
+#[cfg(not(unix))]
+pub fn prepare(_cert: &str, _key: &str, _urls: &[&str]) -> Result<CertHome, String> {
+    Err("Client certificates are only supported on unix".to_string())
+}
+
+/// Bundle the certificate and key as PKCS#12 and import it into a new database in `nssdb`.
+#[cfg(unix)]
+fn import(cert: &str, key: &str, bundle: &Path, nssdb: &Path) -> Result<(), String> {
+    let database = format!("sql:{}", nssdb.to_string_lossy());
+    let bundle_path = bundle.to_string_lossy();
+
+    // created private up front, openssl keeps the mode when it writes into it
+    create_private(bundle).map_err(|e| format!("Failed to create certificate bundle: {}", e))?;
+    run_tool(
+        "openssl",
+        &["pkcs12", "-export", "-in", cert, "-inkey", key, "-out", &bundle_path, "-passout", "pass:"],
+    )?;
+    run_tool("certutil", &["-N", "-d", &database, "--empty-password"])?;
+    run_tool("pk12util", &["-i", &bundle_path, "-d", &database, "-W", ""])?;
+
+    restrict(nssdb).map_err(|e| format!("Failed to restrict certificate database: {}", e))
+}
+
+/// Profile preferences selecting the certificate without a prompt on the origins of `urls`.
+fn preferences(urls: &[&str]) -> serde_json::Value {
+    // only this database's certificate is offered, so an empty filter picks it
+    let exceptions = urls
+        .iter()
+        .filter_map(|url| url::Url::parse(url).ok())
+        .map(|url| url.origin())
+        .filter(|origin| origin.is_tuple())
+        .map(|origin| {
+            let pattern = format!("{},*", origin.ascii_serialization());
+            (pattern, serde_json::json!({ "setting": { "filters": [{}] } }))
+        })
+        .collect::<serde_json::Map<String, serde_json::Value>>();
+
+    serde_json::json!({
+        "profile": { "content_settings": { "exceptions": { "auto_select_certificate": exceptions } } },
+    })
+}
+
+/// Create a new file only the current user can read and write.
+#[cfg(unix)]
+fn create_private(path: &Path) -> std::io::Result<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)
+}
+
+/// Make the files NSS created in `dir` private, whatever the umask was.
+#[cfg(unix)]
+fn restrict(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    for entry in fs::read_dir(dir)? {
+        fs::set_permissions(entry?.path(), fs::Permissions::from_mode(0o600))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn run_tool(tool: &str, args: &[&str]) -> Result<(), String> {
+    let output = std::process::Command::new(tool)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Importing the client certificate needs '{}': {}", tool, e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "'{}' failed to import the client certificate: {}",
+            tool,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Net errors of a failed handshake, e.g. `net::ERR_BAD_SSL_CLIENT_AUTH_CERT`.
+fn is_tls_error(error: &str) -> bool {
+    error.contains("SSL") || error.contains("CERT")
+}
+
+/// Summarize the handshake from the requests seen so far.
+pub fn report(
+    cert_file: &str,
+    log: &Arc<Mutex<NetworkLog>>,
+    documents: &Arc<Mutex<Vec<DocumentResponse>>>,
+) -> ClientCertReport {
+    let tls_errors = log
+        .lock()
+        .map(|log| {
+            log.failed_requests()
+                .into_iter()
+                .filter(|failure| is_tls_error(&failure.error))
+                .map(|failure| format!("{}: {}", failure.url, failure.error))
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+    let served = documents.lock().map(|documents| !documents.is_empty()).unwrap_or(false);
+
+    ClientCertReport {
+        cert_file: cert_file.to_string(),
+        presented: served && tls_errors.is_empty(),
+        tls_errors,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use chromiumoxide::browser::{Browser, BrowserConfig};
+    use futures::StreamExt;
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn selects_the_certificate_for_the_target_origins_only() {
+        let preferences = preferences(&["https://example.com/a?b", "https://localhost:8443/", "about:blank"]);
+        let exceptions = &preferences["profile"]["content_settings"]["exceptions"]["auto_select_certificate"];
+
+        let patterns = exceptions.as_object().unwrap().keys().collect::<Vec<&String>>();
+        assert_eq!(patterns, ["https://example.com,*", "https://localhost:8443,*"]);
+        assert_eq!(exceptions["https://example.com,*"]["setting"]["filters"], serde_json::json!([{}]));
+    }
+
+    fn openssl(args: &[&str]) {
+        let status = Command::new("openssl").args(args).stderr(Stdio::null()).status().unwrap();
+        assert!(status.success(), "openssl {:?} failed", args);
+    }
+
+    fn self_signed(dir: &Path, name: &str) -> (String, String) {
+        let cert = dir.join(format!("{}.pem", name)).to_string_lossy().into_owned();
+        let key = dir.join(format!("{}.key", name)).to_string_lossy().into_owned();
+        openssl(&[
+            "req", "-x509", "-newkey", "rsa:2048", "-nodes", "-days", "1", "-subj", &format!("/CN={}", name),
+            "-keyout", &key, "-out", &cert,
+        ]);
+        (cert, key)
+    }
+
+    /// Chrome fetches a page from an `openssl s_server` that refuses clients without a certificate.
+    #[tokio::test]
+    async fn presents_the_certificate_to_an_mtls_server() {
+        let tools = ["openssl", "certutil", "pk12util"];
+        if let Some(tool) = tools.iter().find(|tool| Command::new(tool).arg("-h").output().is_err()) {
+            eprintln!("skipped, '{}' is not installed", tool);
+            return;
+        }
+        if chromiumoxide::detection::default_executable(Default::default()).is_err() {
+            eprintln!("skipped, Chrome is not installed");
+            return;
+        }
+
+        let dir = std::env::temp_dir().join(format!("client-cert-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let (server_cert, server_key) = self_signed(&dir, "localhost");
+        let (client_cert, client_key) = self_signed(&dir, "client");
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut server = Command::new("openssl")
+            .args(["s_server", "-accept", &port.to_string(), "-cert", &server_cert, "-key", &server_key])
+            .args(["-Verify", "1", "-CAfile", &client_cert, "-www"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        while std::net::TcpStream::connect(("127.0.0.1", port)).is_err() {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+
+        let url = format!("https://localhost:{}/", port);
+        let home = prepare(&client_cert, &client_key, &[&url]).unwrap();
+        let home_path = home.path.clone();
+        assert!(!home_path.join("client.p12").exists());
+        for entry in fs::read_dir(home_path.join(".pki").join("nssdb")).unwrap() {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(entry.unwrap().metadata().unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let config = BrowserConfig::builder()
+            .env("HOME", home.path.to_string_lossy())
+            .user_data_dir(home.user_data_dir())
+            .arg("--ignore-certificate-errors")
+            .build()
+            .unwrap();
+        let (mut browser, mut handler) = Browser::launch(config).await.unwrap();
+        tokio::task::spawn(async move { while handler.next().await.is_some() {} });
+
+        let page = browser.new_page(url).await.unwrap();
+        let content = page.content().await.unwrap();
+        let _ = browser.close().await;
+        let _ = browser.wait().await;
+        let _ = server.kill();
+        let _ = server.wait();
+        drop(home);
+        let _ = fs::remove_dir_all(&dir);
+
+        // s_server -www lists the certificate the client presented
+        assert!(content.contains("Client certificate"), "{}", content);
+        assert!(!home_path.exists());
+    }
+}
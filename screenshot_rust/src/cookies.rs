@@ -0,0 +1,175 @@
+// `--cookie-jar`: cookies loaded before navigation and saved back after the capture.
+//
+// The jar is a JSON array of CDP `CookieParam`s. Concurrent invocations sharing a jar take a
+// `<jar>.lock` file around reading and writing it, and writes go through a rename so a reader
+// never sees a partial jar. Saving merges into the jar as it is on disk then, so captures running
+// side by side keep each other's cookies.
+
+use chromiumoxide::cdp::browser_protocol::network::{
+    Cookie, CookieParam, GetAllCookiesParams, SetCookiesParams, TimeSinceEpoch,
+};
+use chromiumoxide::Page;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// A lock older than this is left over from a crashed run and gets taken over.
+const STALE_LOCK: Duration = Duration::from_secs(30);
+
+/// How many cookies went in and out of the jar.
+#[derive(Serialize, Deserialize, JsonSchema, Default)]
+pub struct CookieJar {
+    pub path: String,
+    pub loaded: usize,
+    pub saved: usize,
+    /// the cookies loaded, so the ones the page deleted can be dropped from the jar.
+    #[serde(skip)]
+    pub loaded_keys: HashSet<CookieKey>,
+    /// the page got to the url, only then is what it set worth saving.
+    #[serde(skip)]
+    pub navigated: bool,
+}
+
+/// A cookie's identity: name, domain and path.
+pub type CookieKey = (String, String, String);
+
+fn key(cookie: &CookieParam) -> CookieKey {
+    (
+        cookie.name.clone(),
+        cookie.domain.clone().unwrap_or_default(),
+        cookie.path.clone().unwrap_or_else(|| "/".to_string()),
+    )
+}
+
+/// Set the cookies stored in the jar at `path` on the page; a missing jar loads nothing.
+pub async fn load(page: &Page, path: &str) -> Result<CookieJar, String> {
+    let cookies = {
+        let _lock = JarLock::acquire(path).await?;
+        read(path)?
+    };
+
+    let jar = CookieJar {
+        path: path.to_string(),
+        loaded: cookies.len(),
+        loaded_keys: cookies.iter().map(key).collect(),
+        ..Default::default()
+    };
+    if !cookies.is_empty() {
+        page.execute(SetCookiesParams::new(cookies))
+            .await
+            .map_err(|e| format!("Failed to set cookies: {}", e))?;
+    }
+
+    Ok(jar)
+}
+
+/// Merge the browser's cookies into the jar: cookies the page set replace those with the same
+/// name, domain and path, cookies it deleted are dropped, and everything else stays.
+pub async fn save(page: &Page, jar: &CookieJar) -> Result<usize, String> {
+    let cookies = page
+        .execute(GetAllCookiesParams::default())
+        .await
+        .map_err(|e| format!("Failed to read cookies: {}", e))?
+        .result
+        .cookies
+        .iter()
+        .map(cookie_param)
+        .collect::<Vec<CookieParam>>();
+
+    let path = jar.path.as_str();
+    let tmp = PathBuf::from(format!("{}.tmp", path));
+
+    // read, merge and write under one lock so no other save slips in between
+    let _lock = JarLock::acquire(path).await?;
+    let merged = merge(read(path)?, cookies, &jar.loaded_keys);
+    let json = serde_json::to_vec_pretty(&merged).map_err(|e| format!("Failed to serialize cookies: {}", e))?;
+    fs::write(&tmp, json).map_err(|e| format!("Failed to write cookie jar '{}': {}", path, e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to write cookie jar '{}': {}", path, e))?;
+
+    Ok(merged.len())
+}
+
+/// The cookies of the jar at `path`, none when it does not exist yet.
+fn read(path: &str) -> Result<Vec<CookieParam>, String> {
+    match fs::read_to_string(path) {
+        Ok(json) => {
+            serde_json::from_str(&json).map_err(|e| format!("Failed to parse cookie jar '{}': {}", path, e))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Failed to read cookie jar '{}': {}", path, e)),
+    }
+}
+
+/// `stored` updated with the browser's `current` cookies, without those loaded and since deleted.
+fn merge(stored: Vec<CookieParam>, current: Vec<CookieParam>, loaded: &HashSet<CookieKey>) -> Vec<CookieParam> {
+    let current_keys = current.iter().map(key).collect::<HashSet<CookieKey>>();
+    let mut merged = stored
+        .into_iter()
+        .filter(|cookie| {
+            let key = key(cookie);
+            !current_keys.contains(&key) && !loaded.contains(&key)
+        })
+        .collect::<Vec<CookieParam>>();
+
+    merged.extend(current);
+    merged
+}
+
+/// The parameters that recreate `cookie`, session cookies staying without an expiry.
+fn cookie_param(cookie: &Cookie) -> CookieParam {
+    CookieParam {
+        name: cookie.name.clone(),
+        value: cookie.value.clone(),
+        domain: Some(cookie.domain.clone()),
+        path: Some(cookie.path.clone()),
+        secure: Some(cookie.secure),
+        http_only: Some(cookie.http_only),
+        same_site: cookie.same_site.clone(),
+        expires: if cookie.session { None } else { Some(TimeSinceEpoch::new(cookie.expires)) },
+        ..CookieParam::new(cookie.name.clone(), cookie.value.clone())
+    }
+}
+
+/// An exclusive `<jar>.lock` file, removed again on drop.
+struct JarLock(PathBuf);
+
+impl JarLock {
+    async fn acquire(jar: &str) -> Result<JarLock, String> {
+        let path = PathBuf::from(format!("{}.lock", jar));
+        let started = tokio::time::Instant::now();
+
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(JarLock(path)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if is_stale(&path) {
+                        let _ = fs::remove_file(&path);
+                        continue;
+                    }
+                    if started.elapsed() >= STALE_LOCK {
+                        return Err(format!("Timed out waiting for the cookie jar lock '{}'", path.display()));
+                    }
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+                Err(e) => return Err(format!("Failed to lock cookie jar '{}': {}", jar, e)),
+            }
+        }
+    }
+}
+
+impl Drop for JarLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn is_stale(lock: &Path) -> bool {
+    fs::metadata(lock)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age > STALE_LOCK)
+}
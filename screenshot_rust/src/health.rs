@@ -0,0 +1,74 @@
+// `--healthcheck`: a cheap end-to-end check of the Chrome pipeline for readiness and liveness probes.
+
+use crate::{chrome_version, launch_browser, Args};
+use chromiumoxide::cdp::browser_protocol::page::{CaptureScreenshotFormat, CaptureScreenshotParams};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// Outcome of a health check.
+#[derive(Serialize, Deserialize)]
+pub struct Health {
+    pub healthy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chrome_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub launch_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture_ms: Option<u64>,
+    pub total_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Launch the browser, open `about:blank` and capture it.
+pub async fn run(args: &Args) -> Health {
+    let started = Instant::now();
+    let mut health = Health {
+        healthy: false,
+        chrome_version: None,
+        launch_ms: None,
+        capture_ms: None,
+        total_ms: 0,
+        error: None,
+    };
+
+    if let Err(e) = check(args, &mut health, started).await {
+        health.error = Some(e);
+    }
+
+    health.total_ms = started.elapsed().as_millis() as u64;
+    health
+}
+
+async fn check(args: &Args, health: &mut Health, started: Instant) -> Result<(), String> {
+    let (mut browser, _cert_home) = launch_browser(args, &[]).await?;
+    health.launch_ms = Some(started.elapsed().as_millis() as u64);
+
+    health.chrome_version = Some(chrome_version(&browser).await?);
+
+    let capture_started = Instant::now();
+    let page = browser
+        .new_page("about:blank")
+        .await
+        .map_err(|e| format!("Failed to create new page: {}", e))?;
+    let screenshot_data = page
+        .screenshot(
+            CaptureScreenshotParams::builder()
+                .format(CaptureScreenshotFormat::Png)
+                .build(),
+        )
+        .await
+        .map_err(|e| format!("Failed to take screenshot: {}", e))?;
+    health.capture_ms = Some(capture_started.elapsed().as_millis() as u64);
+
+    if screenshot_data.is_empty() {
+        return Err("Browser returned an empty screenshot".to_string());
+    }
+
+    let _ = page.close().await;
+    let _ = browser.close().await;
+    let _ = browser.wait().await;
+
+    health.healthy = true;
+    Ok(())
+}
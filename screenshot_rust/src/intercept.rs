@@ -0,0 +1,541 @@
+use chromiumoxide::cdp::browser_protocol::fetch::{
+    ContinueRequestParams, EnableParams, EventRequestPaused, FailRequestParams, FulfillRequestParams,
+    GetResponseBodyParams, HeaderEntry, RequestPattern, RequestStage,
+};
+use chromiumoxide::cdp::browser_protocol::network::{ErrorReason, ResourceType};
+use chromiumoxide::Page;
+use base64::{Engine as _, engine::general_purpose};
+use futures::StreamExt;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// A transparent 1x1 PNG served in place of blocked images.
+const PLACEHOLDER_PNG: &str =
+    "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mNkYAAAAAYAAjCB0C8AAAAASUVORK5CYII=";
+
+/// Requests matching `pattern` are answered with a local file.
+pub struct Mock {
+    /// url glob where `*` matches any run of characters and `?` a single one.
+    pub pattern: String,
+    pub content_type: String,
+    /// the file contents, base64 encoded for `Fetch.fulfillRequest`.
+    body: String,
+}
+
+impl Mock {
+    /// Read the file served for `pattern`.
+    pub fn new(pattern: &str, path: &str) -> Result<Self, String> {
+        let contents = std::fs::read(path).map_err(|e| format!("Failed to read mock file '{}': {}", path, e))?;
+
+        Ok(Mock {
+            pattern: pattern.to_string(),
+            content_type: content_type(path).to_string(),
+            body: general_purpose::STANDARD.encode(contents),
+        })
+    }
+}
+
+/// What to do with the requests of a page.
+#[derive(Default)]
+pub struct InterceptRules {
+    /// answer image responses with a placeholder before their body downloads.
+    pub placeholder_images: bool,
+    /// requests fulfilled from local files, the first matching mock wins.
+    pub mocks: Vec<Mock>,
+    /// strip `<meta http-equiv="refresh">` and `Refresh` headers from documents.
+    pub block_meta_refresh: bool,
+    /// abort requests outside this registrable domain.
+    pub first_party_domain: Option<String>,
+    /// when not empty, abort requests to any host outside these domains and their subdomains.
+    pub allowed_domains: Vec<String>,
+    /// headers set on the main frame's document requests, replacing any Chrome would send.
+    pub navigation_headers: Vec<(String, String)>,
+}
+
+impl InterceptRules {
+    /// Whether any rule needs request interception.
+    pub fn is_active(&self) -> bool {
+        self.placeholder_images
+            || !self.mocks.is_empty()
+            || self.block_meta_refresh
+            || self.first_party_domain.is_some()
+            || !self.allowed_domains.is_empty()
+            || !self.navigation_headers.is_empty()
+    }
+
+    fn patterns(&self) -> Vec<RequestPattern> {
+        let mut patterns = self
+            .mocks
+            .iter()
+            .map(|mock| {
+                RequestPattern::builder()
+                    .url_pattern(mock.pattern.clone())
+                    .request_stage(RequestStage::Request)
+                    .build()
+            })
+            .collect::<Vec<RequestPattern>>();
+
+        if self.first_party_domain.is_some() || !self.allowed_domains.is_empty() {
+            patterns.push(
+                RequestPattern::builder()
+                    .url_pattern("*")
+                    .request_stage(RequestStage::Request)
+                    .build(),
+            );
+        }
+
+        if !self.navigation_headers.is_empty() {
+            patterns.push(
+                RequestPattern::builder()
+                    .resource_type(ResourceType::Document)
+                    .request_stage(RequestStage::Request)
+                    .build(),
+            );
+        }
+
+        if self.placeholder_images {
+            // pause once the headers arrived so the content length can be counted
+            patterns.push(
+                RequestPattern::builder()
+                    .resource_type(ResourceType::Image)
+                    .request_stage(RequestStage::Response)
+                    .build(),
+            );
+        }
+
+        if self.block_meta_refresh {
+            patterns.push(
+                RequestPattern::builder()
+                    .resource_type(ResourceType::Document)
+                    .request_stage(RequestStage::Response)
+                    .build(),
+            );
+        }
+
+        patterns
+    }
+}
+
+/// Counters of what the interception did.
+#[derive(Serialize, Deserialize, Default, Clone, JsonSchema)]
+pub struct InterceptStats {
+    /// images answered with the placeholder.
+    pub images_replaced: usize,
+    /// bytes not downloaded according to the `Content-Length` of replaced images.
+    pub image_bytes_saved: u64,
+    /// urls answered by a `--mock` file.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mocked_requests: Vec<String>,
+    /// meta refreshes and `Refresh` headers removed from documents.
+    pub meta_refreshes_blocked: usize,
+    /// requests aborted for leaving the first party domain.
+    pub third_party_blocked: usize,
+    /// requests aborted for leaving the `--allow-domain` list.
+    pub allowlist_blocked: usize,
+    /// main frame navigations sent with the overridden headers.
+    pub navigations_with_headers: usize,
+}
+
+/// Enable request interception on the page and apply the rules until it closes.
+pub async fn enable(page: &Page, rules: InterceptRules) -> Result<Arc<Mutex<InterceptStats>>, String> {
+    let mut paused = page
+        .event_listener::<EventRequestPaused>()
+        .await
+        .map_err(|e| format!("Failed to listen for paused requests: {}", e))?;
+
+    page.execute(EnableParams::builder().patterns(rules.patterns()).build())
+        .await
+        .map_err(|e| format!("Failed to enable request interception: {}", e))?;
+
+    let stats = Arc::new(Mutex::new(InterceptStats::default()));
+    let task_stats = stats.clone();
+    let page = page.clone();
+
+    tokio::spawn(async move {
+        while let Some(event) = paused.next().await {
+            if !handle(&page, &rules, &event, &task_stats).await {
+                let _ = page
+                    .execute(ContinueRequestParams::new(event.request_id.clone()))
+                    .await;
+            }
+        }
+    });
+
+    Ok(stats)
+}
+
+/// Apply the first matching rule to a paused request, returning whether it was answered.
+async fn handle(page: &Page, rules: &InterceptRules, event: &EventRequestPaused, stats: &Mutex<InterceptStats>) -> bool {
+    let is_response = event.response_status_code.is_some() || event.response_error_reason.is_some();
+
+    if !is_response {
+        if let Some(mock) = rules.mocks.iter().find(|mock| glob_matches(&mock.pattern, &event.request.url)) {
+            if fulfill(page, event, &mock.content_type, mock.body.clone()).await {
+                if let Ok(mut stats) = stats.lock() {
+                    stats.mocked_requests.push(event.request.url.clone());
+                }
+                return true;
+            }
+        }
+
+        if let Some(first_party) = &rules.first_party_domain {
+            let is_third_party = registrable_domain(&event.request.url)
+                .is_some_and(|domain| !domain.eq_ignore_ascii_case(first_party));
+
+            if is_third_party && abort(page, event).await {
+                if let Ok(mut stats) = stats.lock() {
+                    stats.third_party_blocked += 1;
+                }
+                return true;
+            }
+        }
+
+        let is_disallowed =
+            !rules.allowed_domains.is_empty() && !is_allowed(&rules.allowed_domains, &event.request.url);
+
+        if is_disallowed && abort(page, event).await {
+            if let Ok(mut stats) = stats.lock() {
+                stats.allowlist_blocked += 1;
+            }
+            return true;
+        }
+    }
+
+    if !is_response && !rules.navigation_headers.is_empty() && event.resource_type == ResourceType::Document {
+        let is_main_frame = page.mainframe().await.ok().flatten().is_some_and(|frame| frame == event.frame_id);
+
+        if is_main_frame && continue_with_headers(page, event, &rules.navigation_headers).await {
+            if let Ok(mut stats) = stats.lock() {
+                stats.navigations_with_headers += 1;
+            }
+            return true;
+        }
+    }
+
+    if rules.placeholder_images && is_response && event.resource_type == ResourceType::Image {
+        let content_length = event
+            .response_headers
+            .iter()
+            .flatten()
+            .find(|header| header.name.eq_ignore_ascii_case("content-length"))
+            .and_then(|header| header.value.parse::<u64>().ok())
+            .unwrap_or_default();
+
+        if fulfill(page, event, "image/png", PLACEHOLDER_PNG.to_string()).await {
+            if let Ok(mut stats) = stats.lock() {
+                stats.images_replaced += 1;
+                stats.image_bytes_saved += content_length;
+            }
+            return true;
+        }
+    }
+
+    if rules.block_meta_refresh && is_response && event.resource_type == ResourceType::Document {
+        if let Some(blocked) = strip_refresh(page, event).await {
+            if let Ok(mut stats) = stats.lock() {
+                stats.meta_refreshes_blocked += blocked;
+            }
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Re-serve a document without its meta refresh tags and `Refresh` header.
+///
+/// Returns the number of refreshes removed, or `None` when the response was left untouched.
+async fn strip_refresh(page: &Page, event: &EventRequestPaused) -> Option<usize> {
+    let response = page
+        .execute(GetResponseBodyParams::new(event.request_id.clone()))
+        .await
+        .ok()?;
+    let body = if response.result.base64_encoded {
+        String::from_utf8(general_purpose::STANDARD.decode(&response.result.body).ok()?).ok()?
+    } else {
+        response.result.body.clone()
+    };
+
+    let headers = event.response_headers.clone().unwrap_or_default();
+    let refresh_headers = headers
+        .iter()
+        .filter(|header| header.name.eq_ignore_ascii_case("refresh"))
+        .count();
+    let (html, refresh_tags) = strip_meta_refresh(&body);
+
+    if refresh_headers + refresh_tags == 0 {
+        return None;
+    }
+
+    // the body is re-sent decoded so length and encoding headers no longer apply
+    let headers = headers
+        .into_iter()
+        .filter(|header| {
+            !["refresh", "content-length", "content-encoding"]
+                .iter()
+                .any(|name| header.name.eq_ignore_ascii_case(name))
+        })
+        .collect::<Vec<HeaderEntry>>();
+
+    let fulfill = FulfillRequestParams::builder()
+        .request_id(event.request_id.clone())
+        .response_code(event.response_status_code.unwrap_or(200))
+        .response_headers(headers)
+        .body(general_purpose::STANDARD.encode(html))
+        .build()
+        .ok()?;
+
+    page.execute(fulfill).await.ok()?;
+
+    Some(refresh_headers + refresh_tags)
+}
+
+/// Remove every `<meta http-equiv="refresh">` tag from the html.
+fn strip_meta_refresh(html: &str) -> (String, usize) {
+    let lower = html.to_ascii_lowercase();
+    let mut stripped = String::with_capacity(html.len());
+    let mut removed = 0;
+    let mut position = 0;
+
+    while let Some(start) = lower[position..].find("<meta").map(|offset| position + offset) {
+        let end = lower[start..].find('>').map(|offset| start + offset + 1).unwrap_or(lower.len());
+        let tag = &lower[start..end];
+
+        stripped.push_str(&html[position..start]);
+        if attribute(&tag["<meta".len()..], "http-equiv") == Some("refresh") {
+            removed += 1;
+        } else {
+            stripped.push_str(&html[start..end]);
+        }
+        position = end;
+    }
+
+    stripped.push_str(&html[position..]);
+
+    (stripped, removed)
+}
+
+/// The value of the attribute `name` among the lowercase `attributes` of a tag, quoted or not.
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = attributes;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+        let name_end = rest
+            .find(|c: char| c.is_ascii_whitespace() || matches!(c, '=' | '>' | '/'))
+            .unwrap_or(rest.len());
+        if name_end == 0 {
+            return None;
+        }
+        let attribute_name = &rest[..name_end];
+        rest = rest[name_end..].trim_start();
+
+        let value = match rest.strip_prefix('=').map(str::trim_start) {
+            Some(quoted) if quoted.starts_with(['"', '\'']) => {
+                let quote = &quoted[..1];
+                let value_end = quoted[1..].find(quote).map(|offset| offset + 1).unwrap_or(quoted.len());
+                rest = quoted.get(value_end + 1..).unwrap_or_default();
+                &quoted[1..value_end]
+            }
+            Some(unquoted) => {
+                let value_end = unquoted
+                    .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                    .unwrap_or(unquoted.len());
+                rest = &unquoted[value_end..];
+                &unquoted[..value_end]
+            }
+            None => "",
+        };
+
+        if attribute_name == name {
+            return Some(value);
+        }
+    }
+}
+
+/// Continue the paused request with `overrides` replacing headers of the same name.
+async fn continue_with_headers(page: &Page, event: &EventRequestPaused, overrides: &[(String, String)]) -> bool {
+    let mut headers = event
+        .request
+        .headers
+        .inner()
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(name, _)| !overrides.iter().any(|(header, _)| header.eq_ignore_ascii_case(name)))
+        .map(|(name, value)| HeaderEntry::new(name.clone(), value.as_str().unwrap_or_default()))
+        .collect::<Vec<HeaderEntry>>();
+    headers.extend(overrides.iter().map(|(name, value)| HeaderEntry::new(name.clone(), value.clone())));
+
+    match ContinueRequestParams::builder()
+        .request_id(event.request_id.clone())
+        .headers(headers)
+        .build()
+    {
+        Ok(params) => page.execute(params).await.is_ok(),
+        _ => false,
+    }
+}
+
+/// Fail the paused request as blocked by the client.
+async fn abort(page: &Page, event: &EventRequestPaused) -> bool {
+    page.execute(FailRequestParams::new(event.request_id.clone(), ErrorReason::BlockedByClient))
+        .await
+        .is_ok()
+}
+
+/// Whether the url's host is one of the domains or a subdomain of one; non http(s) urls always are.
+fn is_allowed(domains: &[String], url: &str) -> bool {
+    let url = match url::Url::parse(url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => url,
+        _ => return true,
+    };
+    let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+
+    domains.iter().any(|domain| {
+        let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+        host == domain || host.ends_with(&format!(".{}", domain))
+    })
+}
+
+/// The registrable domain (eTLD+1) of an http(s) url per the public suffix list.
+///
+/// `None` for other schemes such as `data:` and `blob:`, which are never third party.
+pub fn registrable_domain(url: &str) -> Option<String> {
+    let url = url::Url::parse(url).ok()?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+
+    let host = url.host_str()?;
+
+    Some(psl::domain_str(host).unwrap_or(host).to_string())
+}
+
+/// Answer the paused request with a 200 and the base64 encoded body.
+async fn fulfill(page: &Page, event: &EventRequestPaused, content_type: &str, body: String) -> bool {
+    match FulfillRequestParams::builder()
+        .request_id(event.request_id.clone())
+        .response_code(200)
+        .response_header(HeaderEntry::new("Content-Type", content_type))
+        .body(body)
+        .build()
+    {
+        Ok(fulfill) => page.execute(fulfill).await.is_ok(),
+        _ => false,
+    }
+}
+
+/// Match a url against a `*` / `?` glob the same way `Fetch.RequestPattern` does.
+fn glob_matches(pattern: &str, url: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let url = url.as_bytes();
+    let (mut p, mut u) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while u < url.len() {
+        if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == url[u]) {
+            p += 1;
+            u += 1;
+        } else if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, u));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            u = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Guess the content type of a mock file from its extension.
+fn content_type(path: &str) -> &'static str {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "json" => "application/json",
+        "html" | "htm" => "text/html; charset=utf-8",
+        "js" | "mjs" => "text/javascript",
+        "css" => "text/css",
+        "txt" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matches_literally() {
+        assert!(glob_matches("https://example.com/", "https://example.com/"));
+        assert!(!glob_matches("https://example.com/", "https://example.com/a"));
+        assert!(!glob_matches("https://example.com/a", "https://example.com/"));
+    }
+
+    #[test]
+    fn glob_star_matches_any_run() {
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("*", "https://example.com/"));
+        assert!(glob_matches("*/api/*", "https://example.com/api/users"));
+        assert!(glob_matches("https://*.example.com/*.js", "https://cdn.example.com/app/main.js"));
+        assert!(!glob_matches("https://*.example.com/*.js", "https://cdn.example.com/app/main.css"));
+        // the star has to give back what a later literal needs
+        assert!(glob_matches("*a*b", "xaxxab"));
+        assert!(glob_matches("**", "anything"));
+    }
+
+    #[test]
+    fn glob_question_mark_matches_one_character() {
+        assert!(glob_matches("https://example.com/?", "https://example.com/a"));
+        assert!(!glob_matches("https://example.com/?", "https://example.com/"));
+        assert!(!glob_matches("https://example.com/?", "https://example.com/ab"));
+    }
+
+    #[test]
+    fn strips_meta_refresh_tags_in_any_case() {
+        let html = r#"<head><META HTTP-EQUIV="Refresh" content="0; url=/next"><title>t</title></head>"#;
+        assert_eq!(strip_meta_refresh(html), ("<head><title>t</title></head>".to_string(), 1));
+
+        let html = "<meta http-equiv=refresh content=5><p>a</p><meta http-equiv='refresh' content='1'>";
+        assert_eq!(strip_meta_refresh(html), ("<p>a</p>".to_string(), 2));
+    }
+
+    #[test]
+    fn keeps_other_meta_tags() {
+        let html = r#"<meta charset="utf-8"><meta name="viewport" content="width=device-width"><p>é</p>"#;
+        assert_eq!(strip_meta_refresh(html), (html.to_string(), 0));
+    }
+
+    #[test]
+    fn matches_the_http_equiv_value_exactly() {
+        let html = r#"<meta name="http-equiv" content="refresh"><meta http-equiv="refresh-rate" content="0">"#;
+        assert_eq!(strip_meta_refresh(html), (html.to_string(), 0));
+
+        let html = r#"<meta content="0; url=/refresh" http-equiv = "REFRESH" /><p>a</p>"#;
+        assert_eq!(strip_meta_refresh(html), ("<p>a</p>".to_string(), 1));
+    }
+
+    #[test]
+    fn strips_an_unterminated_refresh_tag_to_the_end() {
+        assert_eq!(strip_meta_refresh("<p>a</p><meta http-equiv=refresh"), ("<p>a</p>".to_string(), 1));
+    }
+}
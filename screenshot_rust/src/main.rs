@@ -1,17 +1,63 @@
 use chromiumoxide::browser::{Browser, BrowserConfig};
-use chromiumoxide::cdp::browser_protocol::page::CaptureScreenshotParams;
+use chromiumoxide::Page;
+use chromiumoxide::cdp::browser_protocol::page::{
+    AddScriptToEvaluateOnNewDocumentParams, CaptureScreenshotFormat, CaptureScreenshotParams, GetNavigationHistoryParams,
+    NavigateToHistoryEntryParams, ReloadParams, Viewport,
+};
 use futures::StreamExt;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use clap::Parser;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use clap::{CommandFactory, Parser};
 use base64::{Engine as _, engine::general_purpose};
+use chromiumoxide::cdp::browser_protocol::emulation::{
+    ScreenOrientation, ScreenOrientationType, SetDeviceMetricsOverrideParams, SetEmulatedMediaParams,
+    SetFocusEmulationEnabledParams, SetLocaleOverrideParams,
+};
+use chromiumoxide::cdp::browser_protocol::animation::{EnableParams as AnimationEnableParams, SetPlaybackRateParams};
+use chromiumoxide::cdp::browser_protocol::network::EmulateNetworkConditionsParams;
+use chromiumoxide::cdp::js_protocol::runtime::EvaluateParams;
+use serde::de::DeserializeOwned;
 
-#[derive(Parser, Debug)]
+mod batch;
+mod before_after;
+mod cas;
+mod client_cert;
+mod cookies;
+mod health;
+mod http;
+mod intercept;
+mod metadata;
+mod monkey;
+mod network;
+mod overlay;
+mod pdf;
+mod permissions;
+mod placeholder;
+mod postprocess;
+mod prewarm;
+mod preview;
+mod protocol;
+mod ready;
+mod scenario;
+mod scripts;
+mod sign;
+mod template;
+mod tiles;
+mod trace;
+mod window;
+
+#[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// URL to take screenshot of
-    #[arg(short, long)]
-    url: String,
+    #[arg(short, long, required_unless_present_any = ["urls_file", "healthcheck", "print_version", "print_schema", "template_url", "prewarm"])]
+    url: Option<String>,
     
     /// Output file path
     #[arg(short, long, default_value = "screenshot.png")]
@@ -33,17 +79,658 @@ struct Args {
     #[arg(short, long, default_value = "90")]
     quality: u8,
     
-    /// Output format (png, jpeg)
+    /// Output format (png, jpeg, webp, avif, pdf)
     #[arg(short, long, default_value = "png")]
     format: String,
     
     /// Return base64 encoded data instead of saving to file
     #[arg(short, long)]
     base64: bool,
+
+    /// Write the image and JSON result as a binary frame to this file descriptor instead of stdout
+    #[arg(long, value_name = "FD", conflicts_with = "base64")]
+    protocol_fd: Option<i32>,
+
+    /// CSS selector of an element to capture instead of the viewport
+    #[arg(long)]
+    selector: Option<String>,
+
+    /// Zero-based index of the element to capture when the selector matches several
+    #[arg(long, requires = "selector")]
+    element_index: Option<usize>,
+
+    /// Capture every URL listed in this file (one per line) in a single browser session
+    #[arg(long, conflicts_with_all = ["url", "base64", "protocol_fd"])]
+    urls_file: Option<String>,
+
+    /// Directory that batch captures and their manifest are written to
+    #[arg(long, default_value = "screenshots", requires = "urls_file")]
+    output_dir: String,
+
+    /// Also write the batch manifest as manifest.csv for spreadsheets
+    #[arg(long, requires = "urls_file")]
+    output_manifest_csv: bool,
+
+    /// Number of batch captures run at once; the starting point with --concurrency-adaptive
+    #[arg(long, default_value = "1", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), requires = "urls_file")]
+    concurrency: usize,
+
+    /// Raise batch concurrency while captures succeed quickly and halve it on failures or slow captures
+    #[arg(long, requires = "urls_file")]
+    concurrency_adaptive: bool,
+
+    /// Lower bound for --concurrency-adaptive
+    #[arg(long, default_value = "1", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), requires = "concurrency_adaptive")]
+    min_concurrency: usize,
+
+    /// Upper bound for --concurrency-adaptive
+    #[arg(long, default_value = "8", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), requires = "concurrency_adaptive")]
+    max_concurrency: usize,
+
+    /// Ignore query strings when deduplicating batch URLs
+    #[arg(long, requires = "urls_file")]
+    strip_query: bool,
+
+    /// Ignore fragments when deduplicating batch URLs
+    #[arg(long, requires = "urls_file")]
+    strip_fragment: bool,
+
+    /// Resize the viewport width to the document's natural width before capturing
+    #[arg(long)]
+    viewport_from_content: bool,
+
+    /// Upper bound for the width detected by --viewport-from-content
+    #[arg(long, default_value = "3840")]
+    max_content_width: u32,
+
+    /// Render with print media emulation on a viewport matching --paper-size
+    #[arg(long)]
+    print_preview: bool,
+
+    /// Paper size emulated by --print-preview (letter, legal, a4, a3)
+    #[arg(long, default_value = "letter", value_parser = ["letter", "legal", "a4", "a3"])]
+    paper_size: String,
+
+    /// Report requests that failed to load (blocked, CORS, DNS, ...) in the result
+    #[arg(long)]
+    on_request_failed: bool,
+
+    /// Capture the page repeatedly at this interval (e.g. 5s, 500ms, 1m)
+    #[arg(long, value_parser = parse_interval)]
+    capture_interval: Option<Duration>,
+
+    /// How long to keep capturing with --capture-interval
+    #[arg(long, value_parser = parse_duration, default_value = "60s", requires = "capture_interval")]
+    capture_duration: Duration,
+
+    /// Reload the page before every time-lapse capture after the first
+    #[arg(long, requires = "capture_interval")]
+    reload_each: bool,
+
+    /// Answer image downloads with a placeholder instead of aborting them, saving bandwidth without broken-image boxes
+    #[arg(long)]
+    disable_images_but_keep_layout: bool,
+
+    /// Launch a visible browser window instead of headless
+    #[arg(long)]
+    headful: bool,
+
+    /// Capture the whole OS window including tabs and address bar (requires --headful)
+    #[arg(long, requires = "headful", conflicts_with = "selector")]
+    with_browser_chrome: bool,
+
+    /// Fail the capture unless the rendered text contains this string (repeatable)
+    #[arg(long, value_name = "TEXT")]
+    assert_text_present: Vec<String>,
+
+    /// Fail the capture if the rendered text contains this string (repeatable)
+    #[arg(long, value_name = "TEXT")]
+    assert_text_absent: Vec<String>,
+
+    /// Retry a failed capture this many times on a fresh page
+    #[arg(long, default_value = "0")]
+    retries: u32,
+
+    /// Base delay of the exponential backoff between retries
+    #[arg(long, value_parser = parse_duration, default_value = "1s")]
+    retry_delay: Duration,
+
+    /// Randomize each backoff delay between zero and its full value (full jitter)
+    #[arg(long)]
+    retry_jitter: bool,
+
+    /// Seed for randomized behavior such as retry jitter and --monkey
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Clip a normal page screenshot to this element's box so overlapping overlays are kept
+    #[arg(long, conflicts_with = "selector")]
+    clip_selector: Option<String>,
+
+    /// Stop starting new batch captures once this wall-clock budget is spent (e.g. 10m)
+    #[arg(long, value_parser = parse_duration, requires = "urls_file")]
+    max_runtime: Option<Duration>,
+
+    /// Stop a batch run at the first failed capture
+    #[arg(long, requires = "urls_file")]
+    abort_on_first_failure: bool,
+
+    /// Seed Math.random and fake Date.now/performance.now for deterministic rendering; without a value --seed or a random seed is used
+    #[arg(long, value_name = "SEED")]
+    normalize_animations_seed: Option<Option<u32>>,
+
+    /// Grant a permission to the page origin (repeatable): notifications, geolocation, camera, microphone, clipboard
+    #[arg(long, value_name = "PERMISSION", value_parser = permissions::NAMES)]
+    grant_permission: Vec<String>,
+
+    /// Deny a permission to the page origin (repeatable), same names as --grant-permission
+    #[arg(long, value_name = "PERMISSION", value_parser = permissions::NAMES)]
+    deny_permission: Vec<String>,
+
+    /// Re-encode PNG output with this bit depth per sample (8 or 16)
+    #[arg(long, value_parser = parse_bit_depth)]
+    output_bit_depth: Option<u8>,
+
+    /// Re-encode PNG output with this color type; palette produces a smaller indexed PNG
+    #[arg(long, value_parser = ["grayscale", "truecolor", "truecolor-alpha", "palette"])]
+    png_color_type: Option<String>,
+
+    /// Capture from the top of this element down to the bottom of --clip-to
+    #[arg(long, requires = "clip_to", conflicts_with_all = ["selector", "clip_selector"])]
+    clip_from: Option<String>,
+
+    /// Element whose bottom ends the region started by --clip-from
+    #[arg(long, requires = "clip_from")]
+    clip_to: Option<String>,
+
+    /// Answer requests matching a url glob with a local file, as <url-glob>=<file> (repeatable)
+    #[arg(long, value_name = "URL_GLOB=FILE", value_parser = parse_mock)]
+    mock: Vec<(String, String)>,
+
+    /// Follow a <meta http-equiv="refresh"> redirect before capturing, or block it to capture the page that declares it
+    #[arg(long, value_parser = ["follow", "block"])]
+    meta_refresh: Option<String>,
+
+    /// Do not print the JSON result; rely on the exit code and written file, errors still go to stderr
+    #[arg(long, conflicts_with_all = ["base64", "protocol_fd"])]
+    quiet: bool,
+
+    /// Scroll this element into view before a viewport capture
+    #[arg(long, conflicts_with = "full_page")]
+    scroll_to: Option<String>,
+
+    /// Where --scroll-to places the element in the viewport
+    #[arg(long, default_value = "start", value_parser = ["start", "center", "end"], requires = "scroll_to")]
+    scroll_align: String,
+
+    /// Capture Chrome's security interstitial (certificate error, safe browsing) instead of failing
+    #[arg(long)]
+    capture_interstitial: bool,
+
+    /// Id echoed in the result and prefixed to log lines of this capture (a UUID by default)
+    #[arg(long)]
+    request_id: Option<String>,
+
+    /// Turn fixed and sticky elements into normal flow before a full page capture so they appear once
+    #[arg(long, requires = "full_page")]
+    unstick: bool,
+
+    /// Capture every selector listed in this file (one per line) as its own element screenshot
+    #[arg(long, value_name = "PATH")]
+    capture_selector_list_from_file: Option<String>,
+
+    /// Embed the URL, capture time, viewport and tool version into the image (PNG tEXt / JPEG EXIF)
+    #[arg(long)]
+    embed_metadata: bool,
+
+    /// Abort every request outside the registrable domain of the captured URL
+    #[arg(long)]
+    block_third_party: bool,
+
+    /// How long to wait after navigation for dynamic content to load
+    #[arg(long, value_parser = parse_duration, default_value = "2s")]
+    delay: Duration,
+
+    /// Scroll to this percentage (0-100) of the page height before a viewport capture, ahead of --delay
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100), conflicts_with_all = ["full_page", "scroll_to"])]
+    scroll_percent: Option<u8>,
+
+    /// Only allow requests to this domain and its subdomains besides the captured site (repeatable)
+    #[arg(long, value_name = "DOMAIN")]
+    allow_domain: Vec<String>,
+
+    /// Also write a square PNG icon of this many pixels, center-cropped from the capture
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=4096))]
+    icon_size: Option<u32>,
+
+    /// After capturing --url, navigate the same page to this URL and capture again (repeatable, in order)
+    #[arg(long, value_name = "URL", conflicts_with = "capture_interval")]
+    goto: Vec<String>,
+
+    /// Re-encode JPEG output as progressive
+    #[arg(long)]
+    jpeg_progressive: bool,
+
+    /// Re-encode JPEG output with this chroma subsampling; 4:4:4 keeps text sharp
+    #[arg(long, value_parser = ["4:4:4", "4:2:2", "4:2:0"])]
+    jpeg_subsampling: Option<String>,
+
+    /// Launch the browser, capture about:blank and exit 0 when the pipeline works
+    #[arg(long, conflicts_with_all = ["url", "urls_file"])]
+    healthcheck: bool,
+
+    /// Print the tool and Chrome versions and exit
+    #[arg(long, conflicts_with_all = ["url", "urls_file", "healthcheck"])]
+    print_version: bool,
+
+    /// Wait for document.readyState to reach this state before capturing
+    #[arg(long, value_parser = ["interactive", "complete"])]
+    wait_ready_state: Option<String>,
+
+    /// How long to wait for --wait-ready-state
+    #[arg(long, value_parser = parse_duration, default_value = "30s", requires = "wait_ready_state")]
+    ready_state_timeout: Duration,
+
+    /// Perform this many random clicks and scrolls, capturing after each; --seed makes the sequence reproducible
+    #[arg(long, value_name = "N", conflicts_with_all = ["capture_interval", "goto", "navigation_type"])]
+    monkey: Option<usize>,
+
+    /// Set window.name before the page's scripts run
+    #[arg(long)]
+    window_name: Option<String>,
+
+    /// Make the page believe it was opened as a popup from this URL
+    #[arg(long, value_name = "URL")]
+    opener: Option<String>,
+
+    /// Record a Chrome performance trace of navigation and capture to this JSON file
+    #[arg(long, value_name = "PATH")]
+    trace: Option<String>,
+
+    /// Trace categories to record instead of the DevTools performance panel set (comma separated)
+    #[arg(long, value_delimiter = ',', requires = "trace")]
+    trace_categories: Vec<String>,
+
+    /// AVIF encoder effort from 0 (fastest) to 10 (smallest file), only for --format avif.
+    /// WebP takes none: Chrome encodes lossy WebP and the lossless encoder has no effort setting
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=10))]
+    encoding_effort: Option<u8>,
+
+    /// Wait until at least N elements match the selector before capturing
+    #[arg(long, value_name = "SELECTOR=N", value_parser = parse_selector_count)]
+    wait_for_count: Option<(String, usize)>,
+
+    /// How long to wait for --wait-for-count before capturing anyway
+    #[arg(long, value_parser = parse_duration, default_value = "30s", requires = "wait_for_count")]
+    wait_for_count_timeout: Duration,
+
+    /// CSS to inject into the page (repeatable, applied in order)
+    #[arg(long, value_name = "CSS")]
+    style: Vec<String>,
+
+    /// Stylesheet file to inject into the page after any --style (repeatable, applied in order)
+    #[arg(long, value_name = "PATH")]
+    style_file: Vec<String>,
+
+    /// Readiness gate instead of --delay: fast waits for load only; thorough waits for network idle, fonts, images and a stable DOM
+    #[arg(long, value_parser = ["fast", "thorough"])]
+    ready_preset: Option<String>,
+
+    /// Reload after --scroll-percent and capture once the page settles, to check scroll restoration
+    #[arg(long, requires = "scroll_percent")]
+    reload_preserve_scroll: bool,
+
+    /// Print the JSON Schema of the result and exit
+    #[arg(long, conflicts_with_all = ["url", "urls_file", "healthcheck", "print_version"])]
+    print_schema: bool,
+
+    /// Report the computed style of the first element matching this selector
+    #[arg(long, value_name = "SELECTOR")]
+    computed_style: Option<String>,
+
+    /// Properties reported by --computed-style (comma separated, or "all"); defaults to colors, fonts and spacing
+    #[arg(long, value_delimiter = ',', requires = "computed_style")]
+    computed_style_properties: Vec<String>,
+
+    /// Wait this long after launching the browser before the first navigation, e.g. for a proxy to come up
+    #[arg(long, value_parser = parse_duration)]
+    pre_navigate_wait: Option<Duration>,
+
+    /// Wait until an element matching this selector is rendered: a non-empty box and not visibility:hidden
+    #[arg(long, value_name = "SELECTOR")]
+    wait_for_visible: Option<String>,
+
+    /// How long to wait for --wait-for-visible before failing
+    #[arg(long, value_parser = parse_duration, default_value = "30s", requires = "wait_for_visible")]
+    wait_for_visible_timeout: Duration,
+
+    /// When the capture fails, write a placeholder image showing the error to --output
+    #[arg(long, conflicts_with = "base64")]
+    output_placeholder_on_failure: bool,
+
+    /// Capture portrait and then landscape, swapping --width and --height between captures
+    #[arg(long, conflicts_with_all = ["capture_interval", "goto", "navigation_type", "monkey", "print_preview"])]
+    both_orientations: bool,
+
+    /// Turn off CSS animations and transitions with an injected stylesheet before capturing
+    #[arg(long)]
+    freeze_animations: bool,
+
+    /// Stop the animation timeline through CDP and pause Web Animations and requestAnimationFrame loops before capturing
+    #[arg(long)]
+    disable_animations_via_cdp: bool,
+
+    /// Click the first element matching this selector (repeatable, in order); used by --before-after-gif
+    #[arg(long, value_name = "SELECTOR", requires = "before_after_gif")]
+    click: Vec<String>,
+
+    /// Capture before and after the --click interactions and write a GIF toggling between them to this path
+    #[arg(long, value_name = "PATH", requires = "click", conflicts_with_all = ["capture_interval", "goto", "navigation_type", "monkey", "both_orientations"])]
+    before_after_gif: Option<String>,
+
+    /// Load cookies from this JSON file before navigating and save the browser's cookies back after capturing
+    #[arg(long, value_name = "PATH")]
+    cookie_jar: Option<String>,
+
+    /// Capture full pages larger than this many pixels a side in stitched tiles (clipped for WebP)
+    #[arg(long, value_name = "PX", requires = "full_page", value_parser = clap::value_parser!(u32).range(256..))]
+    max_capture_dimension: Option<u32>,
+
+    /// Device scale factor of the viewport, clamped so the output stays within --max-output-pixels
+    #[arg(long)]
+    scale_factor: Option<f64>,
+
+    /// Pixel budget for the viewport at --scale-factor; no side may exceed 16384 either
+    #[arg(long, default_value = "67108864", requires = "scale_factor")]
+    max_output_pixels: u64,
+
+    /// Local HTML template to frame the content of --template-url in
+    #[arg(long, value_name = "PATH", requires = "template_url")]
+    template_file: Option<String>,
+
+    /// Page whose content is injected into --template-file
+    #[arg(long, value_name = "URL", requires = "template_file", conflicts_with_all = ["url", "urls_file"])]
+    template_url: Option<String>,
+
+    /// Element of the template that receives the content
+    #[arg(long, value_name = "SELECTOR", default_value = "#content", requires = "template_file")]
+    template_placeholder: String,
+
+    /// Sec-Fetch-Site header sent with the navigation
+    #[arg(long, value_parser = ["none", "same-origin", "same-site", "cross-site"])]
+    sec_fetch_site: Option<String>,
+
+    /// Sec-Fetch-Mode header sent with the navigation
+    #[arg(long, value_parser = ["navigate", "same-origin", "no-cors", "cors", "websocket"])]
+    sec_fetch_mode: Option<String>,
+
+    /// Sec-Fetch-Dest header sent with the navigation
+    #[arg(long, value_parser = ["document", "iframe", "frame", "embed", "object", "empty"])]
+    sec_fetch_dest: Option<String>,
+
+    /// Also stream the image bytes to stdout, printing the JSON result to stderr instead
+    #[arg(long, conflicts_with_all = ["base64", "urls_file", "protocol_fd"])]
+    tee: bool,
+
+    /// Accept header sent with the navigation, for servers negotiating the variant
+    #[arg(long, value_name = "VALUE")]
+    accept: Option<String>,
+
+    /// Retry the capture when the main document returns one of these HTTP statuses, e.g. 429,503
+    #[arg(long, value_name = "CODES", value_delimiter = ',', requires = "retries")]
+    retry_on_status: Vec<i64>,
+
+    /// Treat the page as focused, so autofocus and :focus-within render like a foreground tab
+    #[arg(long)]
+    emulate_focus: bool,
+
+    /// Also print a small preview of the capture to the terminal
+    #[arg(long, conflicts_with_all = ["urls_file", "protocol_fd", "tee"])]
+    ascii: bool,
+
+    /// When post-processing changes the capture, also save the untouched bytes to a .orig sibling
+    #[arg(long)]
+    keep_original: bool,
+
+    /// After the --goto steps, reload or go back/forward in history and capture again (repeatable, in order)
+    #[arg(long, value_name = "TYPE", value_parser = ["reload", "back", "forward"], conflicts_with = "capture_interval")]
+    navigation_type: Vec<String>,
+
+    /// Refuse to decode captures of more pixels than this for post-processing, and scale placeholders down to it, instead of running out of memory
+    #[arg(long, value_name = "PIXELS")]
+    max_decode_pixels: Option<u64>,
+
+    /// Dim everything but the first element matching this selector
+    #[arg(long, value_name = "SELECTOR")]
+    spotlight: Option<String>,
+
+    /// Opacity of the dimming around --spotlight, 100 blacks it out
+    #[arg(long, default_value = "70", requires = "spotlight", value_parser = clap::value_parser!(u8).range(0..=100))]
+    spotlight_opacity: u8,
+
+    /// PEM client certificate presented to sites asking for mutual TLS
+    #[arg(long, value_name = "FILE", requires = "client_key")]
+    client_cert: Option<String>,
+
+    /// PEM private key of --client-cert
+    #[arg(long, value_name = "FILE", requires = "client_cert")]
+    client_key: Option<String>,
+
+    /// Wait a random extra delay up to this long before capturing, so batch captures don't line up on the same frame
+    #[arg(long, value_parser = parse_duration)]
+    wait_jitter: Option<Duration>,
+
+    /// Sign the saved image and its provenance with this ed25519 key, writing a .sig sidecar
+    #[arg(long, value_name = "KEYFILE", conflicts_with = "base64")]
+    sign: Option<String>,
+
+    /// Fail the capture when more than this percentage of asset responses are 4xx/5xx
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(0..=100))]
+    page_error_on_404_assets: Option<u8>,
+
+    /// Locale the page sees for Intl formatting and navigator.language, e.g. de-DE
+    #[arg(long, value_name = "LOCALE")]
+    locale: Option<String>,
+
+    /// Fail unless a rendered number on the page uses the separators of --locale
+    #[arg(long, requires = "locale")]
+    verify_locale_format: bool,
+
+    /// Find and start Chrome once to warm its caches, print its path and version, and exit
+    #[arg(long, conflicts_with_all = ["url", "urls_file", "healthcheck", "print_version"])]
+    prewarm: bool,
+
+    /// Draw a panel with the load time, resource count and HTTP status onto the capture
+    #[arg(long)]
+    overlay_metrics: bool,
+
+    /// Keep clicking this "load more" button until it is gone, then capture the expanded page
+    #[arg(long, value_name = "SELECTOR")]
+    load_more: Option<String>,
+
+    /// Most clicks on --load-more
+    #[arg(long, default_value = "20", requires = "load_more")]
+    max_clicks: u32,
+
+    /// Time given to the page to load more content after each click
+    #[arg(long, value_parser = parse_duration, default_value = "1s", requires = "load_more")]
+    load_more_interval: Duration,
+
+    /// Store images in this directory named by the SHA-256 of their bytes, with a URL index, instead of --output
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["base64", "output_placeholder_on_failure"])]
+    cas_dir: Option<String>,
+
+    /// Replace the page's viewport meta tag, or add one, and lay the page out as on mobile
+    #[arg(long, value_name = "CONTENT", num_args = 0..=1, default_missing_value = "width=device-width, initial-scale=1")]
+    viewport_meta_override: Option<String>,
+
+    /// After loading, go offline and reload, capturing the offline fallback into -offline
+    #[arg(long, conflicts_with_all = ["capture_interval", "goto", "navigation_type", "monkey", "both_orientations"])]
+    offline_first: bool,
+
+    /// With --offline-first, go back online, reload and capture again into -online
+    #[arg(long, requires = "offline_first")]
+    offline_restore: bool,
+
+    /// In batch mode, relaunch the browser when it crashes and retry the captures it took down
+    #[arg(long, requires = "urls_file")]
+    retry_fresh_browser: bool,
+
+    /// Encode WebP output losslessly, ignoring --quality
+    #[arg(long)]
+    webp_lossless: bool,
+
+    /// Run the steps of this JSON file (goto, wait, click, fill, eval, screenshot) on the page in order
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["capture_interval", "goto", "navigation_type", "monkey", "both_orientations", "offline_first"])]
+    script: Option<String>,
+
+    /// Report the box and visibility of the first element matching this selector
+    #[arg(long, value_name = "SELECTOR")]
+    measure: Option<String>,
+
+    /// Only measure --measure, without capturing or saving an image
+    #[arg(long, requires = "measure", conflicts_with_all = [
+        "base64", "tee", "protocol_fd", "urls_file", "overlay_metrics", "ascii", "icon_size", "embed_metadata",
+        "keep_original", "png_color_type", "output_bit_depth", "jpeg_progressive", "jpeg_subsampling",
+        "encoding_effort", "webp_lossless", "sign", "cas_dir",
+    ])]
+    measure_only: bool,
+}
+
+impl Args {
+    /// Check the combinations of values clap cannot express as attributes.
+    fn validate(&self) -> Result<(), clap::Error> {
+        if self.encoding_effort.is_some() && self.format != "avif" {
+            return Err(Args::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("--encoding-effort requires --format avif, not {}", self.format),
+            ));
+        }
+        if self.webp_lossless && self.format != "webp" {
+            return Err(Args::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("--webp-lossless requires --format webp, not {}", self.format),
+            ));
+        }
+        if self.embed_metadata && !matches!(self.format.as_str(), "png" | "jpeg" | "jpg") {
+            return Err(Args::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("--embed-metadata supports --format png or jpeg, not {}", self.format),
+            ));
+        }
+        if self.overlay_metrics && !matches!(self.format.as_str(), "png" | "jpeg" | "jpg") {
+            return Err(Args::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("--overlay-metrics supports --format png or jpeg, not {}", self.format),
+            ));
+        }
+        if self.sign.is_some() && self.protocol_fd.is_some() && self.cas_dir.is_none() {
+            // nothing is written to --output then, so there is no file to put a sidecar next to
+            return Err(Args::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--sign with --protocol-fd requires --cas-dir to store the image and its signature",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Whether JPEG output is re-encoded after capture instead of kept as Chrome encoded it.
+    fn reencodes_jpeg(&self) -> bool {
+        matches!(self.format.as_str(), "jpeg" | "jpg") && (self.jpeg_progressive || self.jpeg_subsampling.is_some())
+    }
+}
+
+fn parse_mock(value: &str) -> Result<(String, String), String> {
+    match value.rsplit_once('=') {
+        Some((pattern, path)) if !pattern.is_empty() && !path.is_empty() => {
+            Ok((pattern.to_string(), path.to_string()))
+        }
+        _ => Err(format!("invalid mock '{}', expected <url-glob>=<file>", value)),
+    }
+}
+
+fn parse_selector_count(value: &str) -> Result<(String, usize), String> {
+    match value.rsplit_once('=').map(|(selector, count)| (selector, count.trim().parse::<usize>())) {
+        Some((selector, Ok(count))) if !selector.trim().is_empty() => Ok((selector.trim().to_string(), count)),
+        _ => Err(format!("invalid count '{}', expected <selector>=<n>", value)),
+    }
+}
+
+fn parse_bit_depth(value: &str) -> Result<u8, String> {
+    match value {
+        "8" => Ok(8),
+        "16" => Ok(16),
+        _ => Err(format!("unsupported bit depth '{}', expected 8 or 16", value)),
+    }
+}
+
+/// Upper bound for a single backoff delay.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Parse a duration such as `1500ms`, `5s`, `2m` or `1h`; bare numbers are seconds.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: f64 = amount
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", value))?;
+
+    let seconds = match unit {
+        "ms" => amount / 1000.0,
+        "" | "s" => amount,
+        "m" => amount * 60.0,
+        "h" => amount * 3600.0,
+        _ => return Err(format!("invalid duration unit '{}' in '{}'", unit, value)),
+    };
+
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("invalid duration '{}': {}", value, e))
+}
+
+/// A duration that is not zero, so a loop waiting on it makes progress.
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    match parse_duration(value)? {
+        Duration::ZERO => Err(format!("interval '{}' must be longer than zero", value.trim())),
+        interval => Ok(interval),
+    }
+}
+
+impl Args {
+    /// The URL being captured.
+    fn url(&self) -> &str {
+        self.url.as_deref().unwrap_or_default()
+    }
+
+    /// The id correlating the result and log lines of this capture.
+    fn request_id(&self) -> &str {
+        self.request_id.as_deref().unwrap_or_default()
+    }
+
+    /// The headers set on the main frame's document requests.
+    fn navigation_headers(&self) -> Vec<(String, String)> {
+        [
+            ("Sec-Fetch-Site", &self.sec_fetch_site),
+            ("Sec-Fetch-Mode", &self.sec_fetch_mode),
+            ("Sec-Fetch-Dest", &self.sec_fetch_dest),
+            ("Accept", &self.accept),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.as_ref().map(|value| (name.to_string(), value.clone())))
+        .collect()
+    }
+}
+
+/// Print a log line to stderr prefixed with the capture's request id.
+fn log(args: &Args, message: &str) {
+    eprintln!("[{}] {}", args.request_id(), message);
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 struct ScreenshotResult {
+    request_id: String,
     success: bool,
     url: String,
     width: u32,
@@ -55,80 +742,427 @@ struct ScreenshotResult {
     base64_data: Option<String>,
     file_path: Option<String>,
     error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    match_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    element_index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    print_preview: Option<PaperSize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failed_request_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failed_requests: Option<Vec<network::FailedRequest>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timelapse: Option<Vec<TimelapseFrame>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    intercept: Option<intercept::InterceptStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window_capture: Option<window::WindowCapture>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    assertions: Option<Vec<TextAssertion>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attempts: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_delays_ms: Option<Vec<u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clip: Option<Rect>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    animation_seed: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    permissions: Option<Vec<permissions::AppliedPermission>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    png_encoding: Option<postprocess::PngEncoding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jpeg_encoding: Option<postprocess::JpegEncoding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    avif_encoding: Option<postprocess::AvifEncoding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wait_for_count: Option<ElementCount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    injected_styles: Option<InjectedStyles>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    readiness: Option<ready::Readiness>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scroll_restoration: Option<ScrollRestoration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    computed_style: Option<ComputedStyle>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pre_navigate_wait_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wait_for_visible_ms: Option<u64>,
+    /// path of the placeholder written for a failed capture.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    placeholder: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    orientations: Option<Vec<OrientationCapture>>,
+    /// how animations were stopped, in the order applied.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    animations_disabled: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    before_after: Option<before_after::BeforeAfter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cookie_jar: Option<cookies::CookieJar>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capture_fallback: Option<tiles::CaptureFallback>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale_factor: Option<ScaleFactor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    template: Option<template::TemplateInjection>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    navigation_headers: Option<std::collections::BTreeMap<String, String>>,
+    /// the last response to a main frame navigation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response: Option<network::DocumentResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status_retries: Option<StatusRetries>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    focus_emulated: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ascii_preview: Option<preview::AsciiPreview>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    original: Option<postprocess::KeptOriginal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decode_guard: Option<postprocess::DecodeGuard>,
+    /// the box of the --spotlight element left undimmed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spotlight: Option<Rect>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_cert: Option<client_cert::ClientCertReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wait_jitter_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<sign::Signature>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asset_statuses: Option<network::AssetStatuses>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    locale_format: Option<LocaleFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_metrics: Option<overlay::PageMetrics>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    overlay: Option<overlay::Overlay>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    load_more: Option<LoadMore>,
+    /// SHA-256 of the saved image with --cas-dir.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_hash: Option<String>,
+    /// an identical image was already in the --cas-dir store.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_deduplicated: Option<bool>,
+    /// the viewport meta content in effect with --viewport-meta-override.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    viewport_meta: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    network_states: Option<Vec<NetworkStateCapture>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    element_pdf: Option<pdf::ElementPdf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    webp_encoding: Option<postprocess::WebpEncoding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    script_steps: Option<Vec<scenario::StepOutcome>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    measure: Option<Measurement>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta_refresh: Option<MetaRefresh>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scroll_position: Option<ScrollPosition>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interstitial: Option<Interstitial>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unstuck_elements: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    selector_captures: Option<Vec<SelectorCapture>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embedded_metadata: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<postprocess::Icon>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    journey: Option<Vec<JourneyStep>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chrome_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ready_state: Option<ReadyState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    monkey: Option<monkey::Monkey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window_context: Option<WindowContext>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<trace::Trace>,
+    /// shared by the embedded metadata and the signature so both describe the same capture.
+    #[serde(skip)]
+    provenance: Option<metadata::Provenance>,
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
-    
-    let result = take_screenshot(&args).await;
-    
-    // Output result as JSON
-    let json_result = serde_json::to_string_pretty(&result)?;
-    println!("{}", json_result);
-    
-    Ok(())
+/// The `window.name` and opener the page was given.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct WindowContext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    opener: Option<String>,
 }
 
-async fn take_screenshot(args: &Args) -> ScreenshotResult {
-    // Launch browser
-    let (browser, mut handler) = match Browser::launch(
-        BrowserConfig::builder()
-            .build()
-            .unwrap()
-    ).await {
-        Ok(result) => result,
-        Err(e) => {
-            return ScreenshotResult {
-                success: false,
-                url: args.url.clone(),
-                width: args.width,
-                height: args.height,
-                full_page: args.full_page,
-                format: args.format.clone(),
-                quality: args.quality,
-                size: 0,
-                base64_data: None,
-                file_path: None,
-                error: Some(format!("Failed to launch browser: {}", e)),
-            };
-        }
-    };
+/// The stylesheets injected into the page.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct InjectedStyles {
+    /// number of `--style` snippets.
+    inline: usize,
+    files: Vec<String>,
+}
 
-    // Spawn handler task
-    tokio::task::spawn(async move {
-        while let Some(h) = handler.next().await {
-            if h.is_err() {
-                break;
-            }
-        }
-    });
+/// How many elements matched a `--wait-for-count` selector when waiting ended.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ElementCount {
+    selector: String,
+    threshold: usize,
+    count: usize,
+    reached: bool,
+    waited_ms: u64,
+}
 
-    // Create new page
-    let page = match browser.new_page("about:blank").await {
-        Ok(page) => page,
-        Err(e) => {
-            return ScreenshotResult {
-                success: false,
-                url: args.url.clone(),
-                width: args.width,
-                height: args.height,
-                full_page: args.full_page,
-                format: args.format.clone(),
-                quality: args.quality,
-                size: 0,
-                base64_data: None,
-                file_path: None,
-                error: Some(format!("Failed to create new page: {}", e)),
-            };
+/// The `document.readyState` waited for and the one observed.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ReadyState {
+    target: String,
+    observed: String,
+    waited_ms: u64,
+}
+
+/// One capture of a multi-step journey on the same page.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct JourneyStep {
+    step: usize,
+    /// the url navigated to.
+    url: String,
+    /// the url after redirects once the step settled.
+    final_url: Option<String>,
+    /// `reload`, `back` or `forward` for --navigation-type steps.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    navigation_type: Option<String>,
+    file_path: String,
+    size: usize,
+}
+
+/// The capture of one device orientation.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct OrientationCapture {
+    /// `portrait` or `landscape`.
+    orientation: String,
+    width: u32,
+    height: u32,
+    file_path: String,
+    size: usize,
+}
+
+/// The capture after reloading in one network state with `--offline-first`.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct NetworkStateCapture {
+    /// `offline` or `online`.
+    state: String,
+    /// the url the reload settled on, Chrome's error page when nothing served it offline.
+    final_url: Option<String>,
+    file_path: String,
+    size: usize,
+}
+
+/// An element screenshot taken from `--capture-selector-list-from-file`.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct SelectorCapture {
+    selector: String,
+    success: bool,
+    size: usize,
+    file_path: Option<String>,
+    error: Option<String>,
+}
+
+/// A browser interstitial page captured in place of the requested page.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct Interstitial {
+    /// `ssl`, `safe-browsing`, `captive-portal` or `unknown`.
+    kind: String,
+    /// the navigation error that led to the interstitial.
+    error: String,
+}
+
+/// The window scroll offset in CSS pixels.
+#[derive(Serialize, Deserialize, Clone, Copy, JsonSchema)]
+struct ScrollPosition {
+    x: f64,
+    y: f64,
+}
+
+/// The computed style of an element, read right before the capture.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ComputedStyle {
+    selector: String,
+    properties: std::collections::BTreeMap<String, String>,
+}
+
+/// Properties `--computed-style` reports by default, the ones design token audits look at.
+const COMPUTED_STYLE_PROPERTIES: [&str; 16] = [
+    "color",
+    "background-color",
+    "border-color",
+    "font-family",
+    "font-size",
+    "font-weight",
+    "font-style",
+    "line-height",
+    "letter-spacing",
+    "text-transform",
+    "margin",
+    "padding",
+    "gap",
+    "border-width",
+    "border-radius",
+    "box-shadow",
+];
+
+/// The scroll position before a reload and the one the page restored.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ScrollRestoration {
+    before: ScrollPosition,
+    after: ScrollPosition,
+    /// whether the page came back within a pixel of where it was.
+    restored: bool,
+}
+
+/// What happened to a meta refresh on the page.
+#[derive(Serialize, Deserialize, Default, JsonSchema)]
+struct MetaRefresh {
+    /// `follow` or `block`.
+    mode: String,
+    detected: bool,
+    followed: bool,
+    /// where the refresh points to, when detected while following.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+}
+
+/// The device scale factor asked for, the one applied and the viewport output it produces.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ScaleFactor {
+    requested: f64,
+    applied: f64,
+    clamped: bool,
+    output_width: u32,
+    output_height: u32,
+}
+
+/// The numbers of the rendered text checked against the separators of `--locale`.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct LocaleFormat {
+    locale: String,
+    group_separator: String,
+    decimal_separator: String,
+    samples: Vec<LocaleSample>,
+    /// at least one sample uses the locale's separators.
+    passed: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct LocaleSample {
+    text: String,
+    matches: bool,
+}
+
+/// The clicks on the `--load-more` button.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct LoadMore {
+    clicks: u32,
+    /// the button was still there when `--max-clicks` was reached.
+    exhausted: bool,
+}
+
+/// The box of the `--measure` element in CSS pixels relative to the document.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct Measurement {
+    selector: String,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    /// rendered with a size, not `display: none`, `visibility: hidden` or fully transparent.
+    visible: bool,
+    /// at least partly inside the viewport.
+    in_viewport: bool,
+    fully_in_viewport: bool,
+}
+
+/// The main document statuses seen with `--retry-on-status`.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct StatusRetries {
+    /// the status of every attempt that got a response, in order.
+    statuses: Vec<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    final_status: Option<i64>,
+}
+
+/// Largest image side Chrome reliably renders, its GPU texture limit.
+const MAX_OUTPUT_SIDE: f64 = 16384.0;
+
+/// A rectangle in CSS pixels relative to the document.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, JsonSchema)]
+struct Rect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+/// The outcome of a `--assert-text-*` check against the rendered text.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct TextAssertion {
+    /// `present` or `absent`.
+    kind: String,
+    text: String,
+    passed: bool,
+}
+
+/// A single capture of a time-lapse series.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct TimelapseFrame {
+    index: usize,
+    file_path: String,
+    /// capture time in milliseconds since the unix epoch.
+    captured_at_ms: u64,
+    /// milliseconds since the first capture.
+    elapsed_ms: u64,
+}
+
+/// Paper dimensions in CSS pixels at 96 DPI.
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
+struct PaperSize {
+    name: String,
+    width: u32,
+    height: u32,
+}
+
+impl PaperSize {
+    fn from_name(name: &str) -> Self {
+        let (name, width, height) = match name {
+            "legal" => ("legal", 816, 1344),
+            "a4" => ("a4", 794, 1123),
+            "a3" => ("a3", 1123, 1587),
+            _ => ("letter", 816, 1056),
+        };
+
+        PaperSize {
+            name: name.to_string(),
+            width,
+            height,
         }
-    };
+    }
+}
 
-    // Navigate to URL
-    if let Err(e) = page.goto(&args.url).await {
-        return ScreenshotResult {
+impl ScreenshotResult {
+    /// A result describing the requested capture before anything has run.
+    fn new(args: &Args) -> Self {
+        ScreenshotResult {
+            request_id: args.request_id().to_string(),
             success: false,
-            url: args.url.clone(),
+            url: args.url().to_string(),
             width: args.width,
             height: args.height,
             full_page: args.full_page,
@@ -137,107 +1171,1991 @@ async fn take_screenshot(args: &Args) -> ScreenshotResult {
             size: 0,
             base64_data: None,
             file_path: None,
-            error: Some(format!("Failed to navigate to URL: {}", e)),
-        };
+            error: None,
+            match_count: None,
+            element_index: None,
+            content_width: None,
+            print_preview: None,
+            failed_request_count: None,
+            failed_requests: None,
+            timelapse: None,
+            intercept: None,
+            window_capture: None,
+            assertions: None,
+            attempts: None,
+            retry_delays_ms: None,
+            clip: None,
+            animation_seed: None,
+            permissions: None,
+            png_encoding: None,
+            jpeg_encoding: None,
+            avif_encoding: None,
+            wait_for_count: None,
+            injected_styles: None,
+            readiness: None,
+            scroll_restoration: None,
+            computed_style: None,
+            pre_navigate_wait_ms: None,
+            wait_for_visible_ms: None,
+            placeholder: None,
+            orientations: None,
+            animations_disabled: None,
+            before_after: None,
+            cookie_jar: None,
+            capture_fallback: None,
+            scale_factor: None,
+            template: None,
+            navigation_headers: None,
+            response: None,
+            status_retries: None,
+            focus_emulated: None,
+            ascii_preview: None,
+            original: None,
+            decode_guard: None,
+            spotlight: None,
+            client_cert: None,
+            wait_jitter_ms: None,
+            signature: None,
+            asset_statuses: None,
+            locale_format: None,
+            page_metrics: None,
+            overlay: None,
+            load_more: None,
+            content_hash: None,
+            content_deduplicated: None,
+            viewport_meta: None,
+            network_states: None,
+            element_pdf: None,
+            webp_encoding: None,
+            script_steps: None,
+            measure: None,
+            meta_refresh: None,
+            scroll_position: None,
+            interstitial: None,
+            unstuck_elements: None,
+            selector_captures: None,
+            embedded_metadata: None,
+            icon: None,
+            journey: None,
+            chrome_version: None,
+            ready_state: None,
+            monkey: None,
+            window_context: None,
+            trace: None,
+            provenance: None,
+        }
     }
 
-    // Wait for page to load
-    if let Err(e) = page.wait_for_navigation().await {
-        return ScreenshotResult {
-            success: false,
-            url: args.url.clone(),
-            width: args.width,
-            height: args.height,
-            full_page: args.full_page,
-            format: args.format.clone(),
-            quality: args.quality,
-            size: 0,
-            base64_data: None,
-            file_path: None,
-            error: Some(format!("Failed to wait for navigation: {}", e)),
-        };
+    /// The provenance of the capture, fixed the first time it is asked for.
+    fn provenance(&mut self) -> &metadata::Provenance {
+        self.provenance
+            .get_or_insert_with(|| metadata::Provenance::now(&self.url, self.width, self.height))
     }
 
-    // Wait a bit more for dynamic content to load
-    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    /// Mark a stored capture as failed when any text assertion did not hold.
+    fn check_assertions(&mut self) {
+        let failed = self
+            .assertions
+            .iter()
+            .flatten()
+            .filter(|assertion| !assertion.passed)
+            .map(|assertion| format!("expected '{}' to be {}", assertion.text, assertion.kind))
+            .collect::<Vec<String>>();
 
-    // Take screenshot
-    let screenshot_params = CaptureScreenshotParams::builder()
-        .format(match args.format.as_str() {
-            "jpeg" | "jpg" => chromiumoxide::cdp::browser_protocol::page::CaptureScreenshotFormat::Jpeg,
-            _ => chromiumoxide::cdp::browser_protocol::page::CaptureScreenshotFormat::Png,
-        })
-        .quality(if args.format == "jpeg" { args.quality as i64 } else { 90 })
-        .capture_beyond_viewport(args.full_page)
-        .build();
+        if !failed.is_empty() {
+            self.success = false;
+            self.error = Some(format!("Text assertion failed: {}", failed.join(", ")));
+        }
+
+        if let Some(format) = self.locale_format.as_ref().filter(|format| !format.passed) {
+            self.success = false;
+            self.error = Some(format!(
+                "Locale format check failed: no rendered number uses the {} separators",
+                format.locale
+            ));
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = Args::parse();
+    if let Err(e) = args.validate() {
+        e.exit();
+    }
+    if args.request_id.is_none() {
+        args.request_id = Some(uuid::Uuid::new_v4().to_string());
+    }
+    // the template content page is captured like any --url, just framed afterwards
+    if args.template_url.is_some() {
+        args.url = args.template_url.clone();
+    }
+
+    if args.print_schema {
+        println!("{}", serde_json::to_string_pretty(&schemars::schema_for!(ScreenshotResult))?);
+        std::process::exit(0);
+    }
+
+    if args.print_version {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        let (mut browser, cert_home) = launch_browser(&args, &[]).await?;
+        let version = chrome_version(&browser).await;
+        // exit skips destructors, so shut Chrome down here
+        let _ = browser.close().await;
+        let _ = browser.wait().await;
+        drop(cert_home);
+        println!("{}", version?);
+        std::process::exit(0);
+    }
+
+    if args.prewarm {
+        let prewarm = prewarm::run(&args).await;
+        if !args.quiet {
+            println!("{}", serde_json::to_string_pretty(&prewarm)?);
+        } else if let Some(error) = &prewarm.error {
+            log(&args, error);
+        }
+        std::process::exit(if prewarm.ready { 0 } else { 1 });
+    }
+
+    if args.healthcheck {
+        let health = health::run(&args).await;
+        if !args.quiet {
+            println!("{}", serde_json::to_string_pretty(&health)?);
+        } else if let Some(error) = &health.error {
+            log(&args, error);
+        }
+        std::process::exit(if health.healthy { 0 } else { 1 });
+    }
+
+    if args.urls_file.is_some() {
+        let all_succeeded = batch::run(&args).await?;
+        std::process::exit(if all_succeeded { 0 } else { 1 });
+    }
 
-    let screenshot_data = match page.screenshot(screenshot_params).await {
+    let mut result = ScreenshotResult::new(&args);
+    let screenshot_data = match capture(&args, &mut result).await {
         Ok(data) => data,
         Err(e) => {
-            return ScreenshotResult {
-                success: false,
-                url: args.url.clone(),
-                width: args.width,
-                height: args.height,
-                full_page: args.full_page,
-                format: args.format.clone(),
-                quality: args.quality,
-                size: 0,
-                base64_data: None,
-                file_path: None,
-                error: Some(format!("Failed to capture screenshot: {}", e)),
-            };
+            result.error = Some(e);
+            Vec::new()
         }
     };
 
-    let size = screenshot_data.len();
+    if let Some(fd) = args.protocol_fd {
+        // Hand the raw bytes straight to the parent process
+        if result.error.is_none() {
+            match archive_protocol_capture(&args, &screenshot_data, &mut result) {
+                Ok(()) => {
+                    result.success = true;
+                    result.size = screenshot_data.len();
+                    result.check_assertions();
+                }
+                Err(e) => result.error = Some(e),
+            }
+        }
+        let trailer = serde_json::to_vec(&result)?;
+        if let Err(e) = protocol::write_frame(fd, &screenshot_data, &trailer) {
+            log(&args, &format!("Failed to write protocol frame to fd {}: {}", fd, e));
+            std::process::exit(2);
+        }
+        std::process::exit(if result.success { 0 } else { 1 });
+    }
+
+    if result.error.is_none() {
+        save_screenshot(&args, &screenshot_data, &mut result);
+
+        // a preview is a convenience, it never fails the capture
+        if args.ascii && result.success {
+            let preview = postprocess::check_decode_budget(&args, &screenshot_data, &mut result)
+                .and_then(|_| preview::print(&screenshot_data));
+            match preview {
+                Ok(preview) => result.ascii_preview = Some(preview),
+                Err(e) => log(&args, &e),
+            }
+        }
+    } else {
+        write_placeholder(&args, &mut result);
+    }
+
+    // Output result as JSON, on stderr when stdout carries the image
+    if !args.quiet {
+        let json_result = serde_json::to_string_pretty(&result)?;
+        if args.tee {
+            eprintln!("{}", json_result);
+        } else {
+            println!("{}", json_result);
+        }
+    } else if let Some(error) = &result.error {
+        log(&args, error);
+    }
+
+    std::process::exit(if result.success { 0 } else { 1 });
+}
+
+/// Store the captured bytes as base64 or on disk, recording the outcome in the result.
+fn save_screenshot(args: &Args, screenshot_data: &[u8], result: &mut ScreenshotResult) {
+    // nothing was captured, the measurement is the result
+    if args.measure_only {
+        result.success = true;
+        result.check_assertions();
+        return;
+    }
+
+    result.size = screenshot_data.len();
 
     if args.base64 {
         // Return base64 encoded data
-        let base64_data = general_purpose::STANDARD.encode(&screenshot_data);
-        ScreenshotResult {
-            success: true,
-            url: args.url.clone(),
-            width: args.width,
-            height: args.height,
-            full_page: args.full_page,
-            format: args.format.clone(),
-            quality: args.quality,
-            size,
-            base64_data: Some(base64_data),
-            file_path: None,
-            error: None,
-        }
+        result.base64_data = Some(general_purpose::STANDARD.encode(screenshot_data));
+        result.success = true;
     } else {
         // Save to file
-        if let Err(e) = fs::write(&args.output, &screenshot_data) {
-            return ScreenshotResult {
-                success: false,
-                url: args.url.clone(),
-                width: args.width,
-                height: args.height,
-                full_page: args.full_page,
-                format: args.format.clone(),
-                quality: args.quality,
-                size,
-                base64_data: None,
-                file_path: None,
-                error: Some(format!("Failed to save screenshot to file: {}", e)),
-            };
+        let mut file_path = args.output.clone();
+        if let Some(dir) = &args.cas_dir {
+            match store_content(args, dir, screenshot_data, result) {
+                Ok(path) => file_path = path,
+                Err(e) => {
+                    result.error = Some(e);
+                    return;
+                }
+            }
+        } else if let Err(e) = fs::write(&args.output, screenshot_data) {
+            result.error = Some(format!("Failed to save screenshot to file: {}", e));
+            return;
         }
 
-        ScreenshotResult {
-            success: true,
-            url: args.url.clone(),
-            width: args.width,
-            height: args.height,
-            full_page: args.full_page,
-            format: args.format.clone(),
-            quality: args.quality,
-            size,
-            base64_data: None,
-            file_path: Some(args.output.clone()),
-            error: None,
+        if args.tee {
+            let mut stdout = std::io::stdout().lock();
+            if let Err(e) = stdout.write_all(screenshot_data).and_then(|_| stdout.flush()) {
+                result.error = Some(format!("Failed to write screenshot to stdout: {}", e));
+                return;
+            }
+        }
+
+        if let Some(key_file) = &args.sign {
+            if let Err(e) = sign_capture(key_file, screenshot_data, &file_path, result) {
+                result.error = Some(e);
+                return;
+            }
+        }
+
+        result.file_path = Some(file_path);
+        result.success = true;
+    }
+
+    result.check_assertions();
+}
+
+/// Store the bytes in the `--cas-dir` content store, returning the path they are stored under.
+fn store_content(args: &Args, dir: &str, screenshot_data: &[u8], result: &mut ScreenshotResult) -> Result<String, String> {
+    let (hash, path, existed) = cas::store(dir, &result.url, screenshot_data, output_extension(&args.format))?;
+    result.content_hash = Some(hash);
+    result.content_deduplicated = Some(existed);
+
+    Ok(path)
+}
+
+/// Write the `--sign` signature of the bytes next to `file_path`.
+fn sign_capture(key_file: &str, screenshot_data: &[u8], file_path: &str, result: &mut ScreenshotResult) -> Result<(), String> {
+    let signature = sign::sign(screenshot_data, result.provenance(), key_file, file_path)?;
+    result.signature = Some(signature);
+
+    Ok(())
+}
+
+/// Store and sign the bytes handed over on `--protocol-fd` like `save_screenshot` does for a file.
+///
+/// `Args::validate` only lets `--sign` through with `--cas-dir`, the stored file being what the
+/// signature sits next to.
+fn archive_protocol_capture(args: &Args, screenshot_data: &[u8], result: &mut ScreenshotResult) -> Result<(), String> {
+    let Some(dir) = &args.cas_dir else {
+        return Ok(());
+    };
+
+    let file_path = store_content(args, dir, screenshot_data, result)?;
+    result.file_path = Some(file_path.clone());
+    if let Some(key_file) = &args.sign {
+        sign_capture(key_file, screenshot_data, &file_path, result)?;
+    }
+
+    Ok(())
+}
+
+/// Write the `--output-placeholder-on-failure` image for a failed capture.
+///
+/// The result keeps reporting the failure, a placeholder that cannot be written is only logged.
+fn write_placeholder(args: &Args, result: &mut ScreenshotResult) {
+    if !args.output_placeholder_on_failure {
+        return;
+    }
+
+    let error = result.error.clone().unwrap_or_default();
+    match placeholder::write(&args.output, result.width, result.height, args.max_decode_pixels, &error) {
+        Ok(drew_text) => {
+            if !drew_text {
+                log(args, "No system font found, the placeholder is crossed out instead of showing the error");
+            }
+            result.placeholder = Some(args.output.clone());
+        }
+        Err(e) => log(args, &e),
+    }
+}
+
+/// The CDP capture format for the requested output format.
+fn screenshot_format(args: &Args) -> CaptureScreenshotFormat {
+    match args.format.as_str() {
+        "jpeg" | "jpg" => CaptureScreenshotFormat::Jpeg,
+        "webp" if !args.webp_lossless => CaptureScreenshotFormat::Webp,
+        // AVIF and lossless WebP are encoded afterwards from a lossless capture
+        _ => CaptureScreenshotFormat::Png,
+    }
+}
+
+/// Launch a browser for a single capture of `args.url`.
+async fn capture(args: &Args, result: &mut ScreenshotResult) -> Result<Vec<u8>, String> {
+    let (mut browser, _cert_home) = launch_browser(args, &[args.url()]).await?;
+
+    if let Some(wait) = args.pre_navigate_wait {
+        tokio::time::sleep(wait).await;
+        result.pre_navigate_wait_ms = Some(wait.as_millis() as u64);
+    }
+
+    let screenshot_data = take_screenshot(&browser, args, result).await;
+    let _ = browser.close().await;
+    let _ = browser.wait().await;
+
+    screenshot_data
+}
+
+/// Launch the browser and drive its handler in the background.
+///
+/// With a client certificate the browser runs in a private home directory, presenting it to the
+/// origins of `urls`, which is deleted when the returned guard drops; close and wait the browser
+/// first.
+async fn launch_browser(args: &Args, urls: &[&str]) -> Result<(Browser, Option<client_cert::CertHome>), String> {
+    let mut config = BrowserConfig::builder();
+    if args.headful {
+        config = config.with_head();
+    }
+    let cert_home = match (&args.client_cert, &args.client_key) {
+        (Some(cert), Some(key)) => Some(client_cert::prepare(cert, key, urls)?),
+        _ => None,
+    };
+    if let Some(home) = &cert_home {
+        config = config
+            .env("HOME", home.path.to_string_lossy())
+            .user_data_dir(home.user_data_dir());
+    }
+
+    let config = config
+        .build()
+        .map_err(|e| format!("Invalid browser configuration: {}", e))?;
+
+    // Launch browser
+    let (browser, mut handler) = Browser::launch(config)
+        .await
+        .map_err(|e| format!("Failed to launch browser: {}", e))?;
+
+    // Spawn handler task
+    tokio::task::spawn(async move {
+        while let Some(h) = handler.next().await {
+            if h.is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok((browser, cert_home))
+}
+
+/// The browser product and version, e.g. `HeadlessChrome/120.0.6099.109`.
+async fn chrome_version(browser: &Browser) -> Result<String, String> {
+    browser
+        .version()
+        .await
+        .map(|version| version.product)
+        .map_err(|e| format!("Failed to get browser version: {}", e))
+}
+
+/// The file extension of images in `format`.
+fn output_extension(format: &str) -> &'static str {
+    match format {
+        "jpeg" | "jpg" => "jpg",
+        "webp" => "webp",
+        "avif" => "avif",
+        "pdf" => "pdf",
+        _ => "png",
+    }
+}
+
+/// Capture `args.url`, retrying failed attempts with exponential backoff.
+async fn take_screenshot(browser: &Browser, args: &Args, result: &mut ScreenshotResult) -> Result<Vec<u8>, String> {
+    result.chrome_version = chrome_version(browser).await.ok();
+    let mut rng = url_rng(args);
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+        result.response = None;
+        let screenshot_data = take_screenshot_once(browser, args, result).await;
+
+        if args.retries > 0 {
+            result.attempts = Some(attempt);
+        }
+
+        // a listed status fails the attempt, and the server may say when to come back
+        let mut retry_after = None;
+        if !args.retry_on_status.is_empty() {
+            let status = result.response.as_ref().map(|response| response.status);
+            let retries = result.status_retries.get_or_insert_with(|| StatusRetries {
+                statuses: Vec::new(),
+                final_status: None,
+            });
+            retries.statuses.extend(status);
+            retries.final_status = status;
+
+            retry_after = status_retry_after(&args.retry_on_status, result.response.as_ref());
+        }
+
+        match screenshot_data {
+            Err(e) if attempt <= args.retries => {
+                let delay = retry_after.unwrap_or_else(|| retry_backoff(args, attempt, &mut rng));
+                log(
+                    args,
+                    &format!("Attempt {} failed, retrying in {}ms: {}", attempt, delay.as_millis(), e),
+                );
+                result
+                    .retry_delays_ms
+                    .get_or_insert_with(Vec::new)
+                    .push(delay.as_millis() as u64);
+                tokio::time::sleep(delay).await;
+            }
+            screenshot_data => return screenshot_data,
+        }
+    }
+}
+
+/// The RNG behind retry and wait jitter, seeded per URL so concurrent captures spread out.
+fn url_rng(args: &Args) -> StdRng {
+    use std::hash::{Hash, Hasher};
+
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    args.url().hash(&mut hasher);
+
+    StdRng::seed_from_u64(seed ^ hasher.finish())
+}
+
+/// The delay the `Retry-After` of a response with a listed status asks for, none for any other.
+fn status_retry_after(retry_on_status: &[i64], response: Option<&network::DocumentResponse>) -> Option<Duration> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+    response
+        .filter(|response| retry_on_status.contains(&response.status))
+        .and_then(|response| response.retry_after.as_deref())
+        .and_then(|value| parse_retry_after(value, now))
+}
+
+/// A `Retry-After` value, either delay seconds or an HTTP date after `now` in unix seconds,
+/// capped like the backoff.
+fn parse_retry_after(value: &str, now: u64) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds).min(MAX_RETRY_DELAY));
+    }
+
+    let at = http::parse_http_date(value)?;
+
+    Some(Duration::from_secs(at.saturating_sub(now)).min(MAX_RETRY_DELAY))
+}
+
+/// The delay before the retry following `attempt`, with full jitter when enabled.
+fn retry_backoff(args: &Args, attempt: u32, rng: &mut StdRng) -> Duration {
+    let backoff = args
+        .retry_delay
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_RETRY_DELAY);
+
+    if args.retry_jitter {
+        backoff.mul_f64(rng.gen::<f64>())
+    } else {
+        backoff
+    }
+}
+
+/// Capture `args.url` on a fresh page of the browser, closing the page afterwards.
+async fn take_screenshot_once(browser: &Browser, args: &Args, result: &mut ScreenshotResult) -> Result<Vec<u8>, String> {
+    if !args.grant_permission.is_empty() || !args.deny_permission.is_empty() {
+        let origin = url::Url::parse(args.url())
+            .map_err(|e| format!("Invalid URL '{}': {}", args.url(), e))?
+            .origin()
+            .ascii_serialization();
+
+        result.permissions = Some(
+            permissions::apply(browser, &origin, &args.grant_permission, &args.deny_permission).await?,
+        );
+    }
+
+    // Create new page
+    let page = browser
+        .new_page("about:blank")
+        .await
+        .map_err(|e| format!("Failed to create new page: {}", e))?;
+
+    let tracer = if args.trace.is_some() {
+        let categories = if args.trace_categories.is_empty() {
+            trace::DEFAULT_CATEGORIES.iter().map(|c| c.to_string()).collect()
+        } else {
+            args.trace_categories.clone()
+        };
+
+        match trace::start(&page, categories).await {
+            Ok(tracer) => Some(tracer),
+            Err(e) => {
+                let _ = page.close().await;
+                return Err(e);
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut screenshot_data = screenshot_page(&page, args, result).await;
+
+    // Stop tracing even when the capture failed, the trace is most useful then
+    if let (Some(tracer), Some(path)) = (tracer, &args.trace) {
+        match tracer.stop(&page, path).await {
+            Ok(trace) => result.trace = Some(trace),
+            Err(e) => screenshot_data = screenshot_data.and(Err(e)),
+        }
+    }
+
+    // Save whatever the page set, a failed capture can still have logged in, but never over a jar
+    // that failed to load or from a page that did not get to the url
+    if let Some(jar) = result.cookie_jar.as_mut().filter(|jar| jar.navigated) {
+        match cookies::save(&page, jar).await {
+            Ok(saved) => jar.saved = saved,
+            Err(e) => screenshot_data = screenshot_data.and(Err(e)),
+        }
+    }
+
+    // --measure-only captured nothing to process
+    let screenshot_data = if args.measure_only {
+        screenshot_data
+    } else {
+        screenshot_data.and_then(|screenshot_data| postprocess::apply(args, screenshot_data, result))
+    };
+    let _ = page.close().await;
+
+    screenshot_data
+}
+
+/// Evaluate a JavaScript expression on the page, awaiting promises and returning the value.
+async fn evaluate<T: DeserializeOwned>(page: &Page, expression: &str) -> Result<T, String> {
+    let params = EvaluateParams::builder()
+        .expression(expression)
+        .await_promise(true)
+        .return_by_value(true)
+        .build()?;
+
+    page.evaluate_expression(params)
+        .await
+        .map_err(|e| format!("Failed to evaluate script: {}", e))?
+        .into_value()
+        .map_err(|e| format!("Failed to read script result: {}", e))
+}
+
+/// The document relative box of the first element matching `selector`, if any.
+async fn element_rect(page: &Page, selector: &str) -> Result<Option<Rect>, String> {
+    let selector = serde_json::to_string(selector).map_err(|e| e.to_string())?;
+
+    evaluate(
+        page,
+        &format!(
+            r#"(() => {{
+                const element = document.querySelector({});
+                if (!element) return null;
+                const rect = element.getBoundingClientRect();
+                return {{ x: rect.left + window.scrollX, y: rect.top + window.scrollY, width: rect.width, height: rect.height }};
+            }})()"#,
+            selector
+        ),
+    )
+    .await
+}
+
+/// Click the button matching `selector` until it is gone, hidden or disabled, or `--max-clicks` is reached.
+async fn load_more(page: &Page, args: &Args, selector: &str) -> Result<LoadMore, String> {
+    let expression = format!(
+        r#"(() => {{
+            const button = document.querySelector({});
+            if (!button || button.disabled || button.getClientRects().length === 0) return false;
+            button.scrollIntoView({{ block: 'center' }});
+            button.click();
+            return true;
+        }})()"#,
+        serde_json::to_string(selector).map_err(|e| e.to_string())?
+    );
+
+    let mut clicks = 0;
+    while clicks < args.max_clicks {
+        let clicked: bool = evaluate(page, &expression).await?;
+        if !clicked {
+            return Ok(LoadMore {
+                clicks,
+                exhausted: false,
+            });
         }
+
+        clicks += 1;
+        tokio::time::sleep(args.load_more_interval).await;
+    }
+
+    Ok(LoadMore { clicks, exhausted: true })
+}
+
+/// Compare the numbers in the rendered text with the separators Intl uses for `locale`.
+///
+/// A separator followed by one or two digits is read as the decimal one, any other as grouping.
+async fn locale_format(page: &Page, locale: &str) -> Result<LocaleFormat, String> {
+    evaluate(
+        page,
+        &format!(
+            r#"(() => {{
+                const locale = {};
+                const parts = new Intl.NumberFormat(locale).formatToParts(1234567.5);
+                const group = (parts.find(part => part.type === 'group') || {{ value: '' }}).value;
+                const decimal = (parts.find(part => part.type === 'decimal') || {{ value: '.' }}).value;
+                const text = document.body ? document.body.innerText : '';
+                const numbers = text.match(/\d{{1,3}}(?:[.,'\u00a0\u202f\u2019 ]\d{{3}})+(?:[.,]\d{{1,2}})?|\d+[.,]\d{{1,2}}(?!\d)/g) || [];
+                const samples = numbers.slice(0, 20).map(sample => {{
+                    const separators = sample.match(/[^\d]/g) || [];
+                    const last = sample.search(/[^\d]\d{{1,2}}$/);
+                    const decimalSeparator = last >= 0 ? sample[last] : null;
+                    const groups = decimalSeparator === null ? separators : separators.slice(0, -1);
+                    const matches = (decimalSeparator === null || decimalSeparator === decimal)
+                        && groups.every(separator => separator === group);
+                    return {{ text: sample, matches }};
+                }});
+                return {{
+                    locale,
+                    group_separator: group,
+                    decimal_separator: decimal,
+                    samples,
+                    passed: samples.some(sample => sample.matches),
+                }};
+            }})()"#,
+            serde_json::to_string(locale).map_err(|e| e.to_string())?,
+        ),
+    )
+    .await
+}
+
+/// Run a script in every new document of the page before its own scripts.
+async fn add_init_script(page: &Page, source: String) -> Result<(), String> {
+    page.execute(AddScriptToEvaluateOnNewDocumentParams::new(source))
+        .await
+        .map_err(|e| format!("Failed to install init script: {}", e))?;
+
+    Ok(())
+}
+
+/// Clamp `args.scale_factor` so a `width` x `height` viewport stays within the pixel budget.
+fn scale_factor(args: &Args, width: u32, height: u32) -> Option<ScaleFactor> {
+    args.scale_factor
+        .map(|requested| clamp_scale_factor(requested, width, height, args.max_output_pixels))
+}
+
+/// Clamp `requested` so a `width` x `height` viewport stays within `max_output_pixels`.
+fn clamp_scale_factor(requested: f64, width: u32, height: u32, max_output_pixels: u64) -> ScaleFactor {
+    let (width, height) = (width.max(1) as f64, height.max(1) as f64);
+    let limit = (max_output_pixels as f64 / (width * height))
+        .sqrt()
+        .min(MAX_OUTPUT_SIDE / width.max(height));
+    // clamp passes NaN through, Chrome would get it as is
+    let applied = if requested.is_nan() { 1.0 } else { requested }.clamp(0.1, limit.max(0.1));
+
+    ScaleFactor {
+        requested,
+        applied,
+        clamped: applied != requested,
+        output_width: (width * applied).round() as u32,
+        output_height: (height * applied).round() as u32,
+    }
+}
+
+/// The device scale factor applied to the page.
+fn applied_scale(result: &ScreenshotResult) -> f64 {
+    result.scale_factor.as_ref().map_or(1.0, |scale| scale.applied)
+}
+
+/// Override the page viewport size, as a mobile viewport when the viewport meta tag should apply.
+async fn set_viewport(page: &Page, args: &Args, width: u32, height: u32, scale: f64) -> Result<(), String> {
+    let mobile = args.viewport_meta_override.is_some();
+    page.execute(SetDeviceMetricsOverrideParams::new(width as i64, height as i64, scale, mobile))
+        .await
+        .map_err(|e| format!("Failed to set viewport to {}x{}: {}", width, height, e))?;
+
+    Ok(())
+}
+
+async fn screenshot_page(page: &Page, args: &Args, result: &mut ScreenshotResult) -> Result<Vec<u8>, String> {
+    let paper = args.print_preview.then(|| PaperSize::from_name(&args.paper_size));
+    let (width, height) = paper.as_ref().map_or((args.width, args.height), |paper| (paper.width, paper.height));
+
+    // Settle the output size up front rather than failing after the page loaded
+    result.scale_factor = scale_factor(args, width, height);
+    if let Some(scale) = result.scale_factor.as_ref().filter(|scale| scale.clamped) {
+        log(
+            args,
+            &format!(
+                "Scale factor {} would exceed the output budget, clamped to {:.3} ({}x{})",
+                scale.requested, scale.applied, scale.output_width, scale.output_height
+            ),
+        );
+    }
+    let scale = applied_scale(result);
+
+    if let Some(paper) = paper {
+        // Match the viewport to the paper and render the print stylesheet
+        set_viewport(page, args, paper.width, paper.height, scale).await?;
+        page.execute(SetEmulatedMediaParams::builder().media("print").build())
+            .await
+            .map_err(|e| format!("Failed to emulate print media: {}", e))?;
+
+        result.width = paper.width;
+        result.height = paper.height;
+        result.print_preview = Some(paper);
+    } else {
+        set_viewport(page, args, args.width, args.height, scale).await?;
+    }
+
+    if let Some(locale) = &args.locale {
+        page.execute(SetLocaleOverrideParams::builder().locale(locale.clone()).build())
+            .await
+            .map_err(|e| format!("Failed to override locale: {}", e))?;
+    }
+
+    if args.emulate_focus {
+        page.execute(SetFocusEmulationEnabledParams::new(true))
+            .await
+            .map_err(|e| format!("Failed to emulate focus: {}", e))?;
+        result.focus_emulated = Some(true);
+    }
+
+    if let Some(seed) = args.normalize_animations_seed {
+        let seed = seed
+            .or(args.seed.map(|seed| seed as u32))
+            .unwrap_or_else(rand::random);
+
+        add_init_script(page, scripts::deterministic(seed)).await?;
+        result.animation_seed = Some(seed);
+    }
+
+    if !args.style.is_empty() || !args.style_file.is_empty() {
+        for css in &args.style {
+            add_init_script(page, scripts::inject_style(css)).await?;
+        }
+        for path in &args.style_file {
+            let css = fs::read_to_string(path).map_err(|e| format!("Failed to read style file '{}': {}", path, e))?;
+            add_init_script(page, scripts::inject_style(&css)).await?;
+        }
+
+        result.injected_styles = Some(InjectedStyles {
+            inline: args.style.len(),
+            files: args.style_file.clone(),
+        });
+    }
+
+    if let Some(content) = &args.viewport_meta_override {
+        add_init_script(page, scripts::viewport_meta(content)).await?;
+    }
+
+    if args.window_name.is_some() || args.opener.is_some() {
+        if let Some(opener) = &args.opener {
+            url::Url::parse(opener).map_err(|e| format!("Invalid opener URL '{}': {}", opener, e))?;
+        }
+
+        add_init_script(page, scripts::window_context(args.window_name.as_deref(), args.opener.as_deref())).await?;
+        result.window_context = Some(WindowContext {
+            name: args.window_name.clone(),
+            opener: args.opener.clone(),
+        });
+    }
+
+    let network_log = if args.on_request_failed {
+        Some(network::watch(page).await?)
+    } else {
+        None
+    };
+
+    let intercept_rules = intercept::InterceptRules {
+        placeholder_images: args.disable_images_but_keep_layout,
+        mocks: args
+            .mock
+            .iter()
+            .map(|(pattern, path)| intercept::Mock::new(pattern, path))
+            .collect::<Result<Vec<_>, String>>()?,
+        block_meta_refresh: args.meta_refresh.as_deref() == Some("block"),
+        first_party_domain: if args.block_third_party {
+            Some(
+                intercept::registrable_domain(args.url())
+                    .ok_or_else(|| format!("Cannot determine the domain of '{}'", args.url()))?,
+            )
+        } else {
+            None
+        },
+        allowed_domains: if args.allow_domain.is_empty() {
+            Vec::new()
+        } else {
+            // the captured site itself is always allowed
+            let mut allowed_domains = args.allow_domain.clone();
+            allowed_domains.extend(intercept::registrable_domain(args.url()));
+            allowed_domains
+        },
+        navigation_headers: args.navigation_headers(),
+    };
+    if !intercept_rules.navigation_headers.is_empty() {
+        result.navigation_headers = Some(intercept_rules.navigation_headers.iter().cloned().collect());
+    }
+    let intercept_stats = if intercept_rules.is_active() {
+        Some(intercept::enable(page, intercept_rules).await?)
+    } else {
+        None
+    };
+
+    let documents = network::watch_documents(page).await?;
+    let asset_statuses = if args.page_error_on_404_assets.is_some() {
+        Some(network::watch_asset_statuses(page).await?)
+    } else {
+        None
+    };
+    let tls_log = if args.client_cert.is_some() {
+        Some(network::watch(page).await?)
+    } else {
+        None
+    };
+
+    // Track requests from the start so idleness covers the whole load
+    let in_flight = if args.ready_preset.as_deref() == Some("thorough") {
+        Some(network::track_in_flight(page).await?)
+    } else {
+        None
+    };
+
+    // a jar loaded by an earlier attempt must not be saved if this one fails to load it
+    result.cookie_jar = None;
+    if let Some(path) = &args.cookie_jar {
+        result.cookie_jar = Some(cookies::load(page, path).await?);
     }
-}
\ No newline at end of file
+
+    // Navigate to URL
+    match page.goto(args.url()).await {
+        Ok(_) => {
+            // Wait for page to load
+            page.wait_for_navigation()
+                .await
+                .map_err(|e| format!("Failed to wait for navigation: {}", e))?;
+            if let Some(jar) = result.cookie_jar.as_mut() {
+                jar.navigated = true;
+            }
+        }
+        Err(e) if args.capture_interstitial => {
+            let error = e.to_string();
+            result.interstitial = Some(
+                detect_interstitial(page, &error)
+                    .await
+                    .ok_or_else(|| format!("Failed to navigate to URL: {}", error))?,
+            );
+        }
+        Err(e) => {
+            if let (Some(cert), Some(log)) = (&args.client_cert, &tls_log) {
+                result.client_cert = Some(client_cert::report(cert, log, &documents));
+            }
+            return Err(format!("Failed to navigate to URL: {}", e));
+        }
+    }
+
+    if let (Some(cert), Some(log)) = (&args.client_cert, &tls_log) {
+        result.client_cert = Some(client_cert::report(cert, log, &documents));
+    }
+
+    if !args.retry_on_status.is_empty() {
+        result.response = documents.lock().ok().and_then(|documents| documents.last().cloned());
+        if let Some(response) = result.response.as_ref().filter(|r| args.retry_on_status.contains(&r.status)) {
+            return Err(format!("Main document returned HTTP {}", response.status));
+        }
+    }
+
+    if let Some(target) = &args.wait_ready_state {
+        result.ready_state = Some(wait_ready_state(page, target, args.ready_state_timeout).await?);
+    }
+
+    if let Some(selector) = &args.wait_for_visible {
+        result.wait_for_visible_ms = Some(wait_for_visible(page, selector, args.wait_for_visible_timeout).await?);
+    }
+
+    if let Some((selector, threshold)) = &args.wait_for_count {
+        result.wait_for_count = Some(wait_for_count(page, selector, *threshold, args.wait_for_count_timeout).await?);
+    }
+
+    if args.meta_refresh.as_deref() == Some("follow") {
+        result.meta_refresh = Some(follow_meta_refresh(page).await?);
+    }
+
+    // Scroll first so lazy content at that position loads during the delay
+    if let Some(percent) = args.scroll_percent {
+        let position: ScrollPosition = evaluate(
+            page,
+            &format!(
+                r#"(() => {{
+                    const scrollable = document.documentElement.scrollHeight - window.innerHeight;
+                    window.scrollTo(0, Math.max(0, scrollable) * {} / 100);
+                    return {{ x: window.scrollX, y: window.scrollY }};
+                }})()"#,
+                percent
+            ),
+        )
+        .await?;
+
+        result.scroll_position = Some(position);
+    }
+
+    let scroll_before_reload = match result.scroll_position {
+        Some(position) if args.reload_preserve_scroll => {
+            page.reload()
+                .await
+                .map_err(|e| format!("Failed to reload page: {}", e))?;
+            Some(position)
+        }
+        _ => None,
+    };
+
+    if let Some(in_flight) = &in_flight {
+        result.readiness = Some(ready::thorough(page, in_flight).await?);
+    } else if args.ready_preset.as_deref() == Some("fast") {
+        result.readiness = Some(ready::fast(page).await?);
+    } else {
+        // Wait a bit more for dynamic content to load
+        tokio::time::sleep(args.delay).await;
+    }
+
+    if args.viewport_meta_override.is_some() {
+        result.viewport_meta = evaluate(
+            page,
+            "(() => { const meta = document.querySelector('meta[name=viewport]'); return meta ? meta.content : null; })()",
+        )
+        .await?;
+    }
+
+    if let Some(selector) = &args.load_more {
+        result.load_more = Some(load_more(page, args, selector).await?);
+    }
+
+    if let Some(max) = args.wait_jitter {
+        let jitter = max.mul_f64(url_rng(args).gen::<f64>());
+        tokio::time::sleep(jitter).await;
+        result.wait_jitter_ms = Some(jitter.as_millis() as u64);
+    }
+
+    if let Some(before) = scroll_before_reload {
+        let after: ScrollPosition = evaluate(page, "({ x: window.scrollX, y: window.scrollY })").await?;
+
+        result.scroll_restoration = Some(ScrollRestoration {
+            before,
+            after,
+            restored: (after.x - before.x).abs() <= 1.0 && (after.y - before.y).abs() <= 1.0,
+        });
+    }
+
+    result.response = documents.lock().ok().and_then(|documents| documents.last().cloned());
+
+    if let (Some(threshold), Some(statuses)) = (args.page_error_on_404_assets, &asset_statuses) {
+        let statuses = statuses.lock().map(|statuses| statuses.clone()).unwrap_or_default();
+        let ratio = statuses.ratio;
+        result.asset_statuses = Some(statuses);
+
+        if ratio * 100.0 > threshold as f64 {
+            return Err(format!(
+                "{:.1}% of asset responses failed, more than the allowed {}%",
+                ratio * 100.0,
+                threshold
+            ));
+        }
+    }
+
+    if let Some(template) = &args.template_file {
+        result.template = Some(template::apply(page, args, template).await?);
+    }
+
+    // Fit the viewport width to the content
+    if args.viewport_from_content {
+        let natural_width: f64 = evaluate(
+            page,
+            "Math.max(document.documentElement.scrollWidth, document.body ? document.body.scrollWidth : 0)",
+        )
+        .await?;
+        let width = (natural_width.ceil() as u32).clamp(1, args.max_content_width);
+
+        set_viewport(page, args, width, args.height, applied_scale(result)).await?;
+        result.content_width = Some(width);
+        result.width = width;
+    }
+
+    if let Some(log) = &network_log {
+        if let Ok(log) = log.lock() {
+            let failed_requests = log.failed_requests();
+            result.failed_request_count = Some(failed_requests.len());
+            result.failed_requests = Some(failed_requests);
+        }
+    }
+
+    if !args.assert_text_present.is_empty() || !args.assert_text_absent.is_empty() {
+        let text: String = evaluate(page, "document.body ? document.body.innerText : ''").await?;
+        let present = args.assert_text_present.iter().map(|expected| TextAssertion {
+            kind: "present".to_string(),
+            text: expected.clone(),
+            passed: text.contains(expected.as_str()),
+        });
+        let absent = args.assert_text_absent.iter().map(|unexpected| TextAssertion {
+            kind: "absent".to_string(),
+            text: unexpected.clone(),
+            passed: !text.contains(unexpected.as_str()),
+        });
+
+        result.assertions = Some(present.chain(absent).collect());
+    }
+
+    if let (true, Some(locale)) = (args.verify_locale_format, &args.locale) {
+        result.locale_format = Some(locale_format(page, locale).await?);
+    }
+
+    if let Some(selector) = &args.scroll_to {
+        let position: Option<ScrollPosition> = evaluate(
+            page,
+            &format!(
+                r#"(() => {{
+                    const element = document.querySelector({});
+                    if (!element) return null;
+                    element.scrollIntoView({{ block: {}, inline: 'nearest' }});
+                    return {{ x: window.scrollX, y: window.scrollY }};
+                }})()"#,
+                serde_json::to_string(selector).map_err(|e| e.to_string())?,
+                serde_json::to_string(&args.scroll_align).map_err(|e| e.to_string())?,
+            ),
+        )
+        .await?;
+
+        result.scroll_position =
+            Some(position.ok_or_else(|| format!("Scroll target '{}' matched no element", selector))?);
+    }
+
+    if args.unstick {
+        // fixed elements keep their offsets against the page instead of the viewport,
+        // sticky ones fall back to their natural position
+        let unstuck: u32 = evaluate(
+            page,
+            r#"(() => {
+                let count = 0;
+                for (const element of document.querySelectorAll('body *')) {
+                    const position = getComputedStyle(element).position;
+                    if (position === 'fixed') {
+                        element.style.setProperty('position', 'absolute', 'important');
+                        count++;
+                    } else if (position === 'sticky') {
+                        element.style.setProperty('position', 'relative', 'important');
+                        count++;
+                    }
+                }
+                return count;
+            })()"#,
+        )
+        .await?;
+
+        result.unstuck_elements = Some(unstuck);
+    }
+
+    if let Some(selector) = &args.spotlight {
+        // four overlays around the element's box, so the element itself keeps its own pixels
+        let spotlight: Option<Rect> = evaluate(
+            page,
+            &format!(
+                r#"(() => {{
+                    const element = document.querySelector({});
+                    if (!element) return null;
+                    const rect = element.getBoundingClientRect();
+                    const box = {{ x: rect.left + window.scrollX, y: rect.top + window.scrollY, width: rect.width, height: rect.height }};
+                    const root = document.documentElement;
+                    const width = Math.max(root.scrollWidth, box.x + box.width);
+                    const height = Math.max(root.scrollHeight, box.y + box.height);
+                    const shade = (left, top, w, h) => {{
+                        const overlay = document.createElement('div');
+                        overlay.style.cssText = `position:absolute;left:${{left}}px;top:${{top}}px;width:${{Math.max(w, 0)}}px;height:${{Math.max(h, 0)}}px;background:rgba(0,0,0,{});z-index:2147483647;pointer-events:none`;
+                        document.body.appendChild(overlay);
+                    }};
+                    shade(0, 0, width, box.y);
+                    shade(0, box.y + box.height, width, height - box.y - box.height);
+                    shade(0, box.y, box.x, box.height);
+                    shade(box.x + box.width, box.y, width - box.x - box.width, box.height);
+                    return box;
+                }})()"#,
+                serde_json::to_string(selector).map_err(|e| e.to_string())?,
+                args.spotlight_opacity as f64 / 100.0,
+            ),
+        )
+        .await?;
+
+        result.spotlight = Some(spotlight.ok_or_else(|| format!("Spotlight selector '{}' matched no element", selector))?);
+    }
+
+    if let Some(stats) = &intercept_stats {
+        if let Ok(stats) = stats.lock() {
+            if args.meta_refresh.as_deref() == Some("block") {
+                result.meta_refresh = Some(MetaRefresh {
+                    mode: "block".to_string(),
+                    detected: stats.meta_refreshes_blocked > 0,
+                    ..Default::default()
+                });
+            }
+            result.intercept = Some(stats.clone());
+        }
+    }
+
+    if let Some(path) = &args.capture_selector_list_from_file {
+        result.selector_captures = Some(capture_selector_list(page, args, path).await?);
+    }
+
+    if args.freeze_animations || args.disable_animations_via_cdp {
+        result.animations_disabled = Some(disable_animations(page, args).await?);
+    }
+
+    if let Some(selector) = &args.computed_style {
+        result.computed_style = Some(computed_style(page, selector, &args.computed_style_properties).await?);
+    }
+
+    if args.overlay_metrics {
+        let (load_ms, resources): (Option<f64>, u32) = evaluate(
+            page,
+            r#"(() => {
+                const navigation = performance.getEntriesByType('navigation')[0];
+                const load = navigation && navigation.loadEventEnd > 0 ? navigation.loadEventEnd : null;
+                return [load, performance.getEntriesByType('resource').length];
+            })()"#,
+        )
+        .await?;
+        let status = documents
+            .lock()
+            .ok()
+            .and_then(|documents| documents.last().map(|document| document.status));
+
+        result.page_metrics = Some(overlay::PageMetrics {
+            load_ms,
+            resources,
+            status,
+        });
+    }
+
+    if let Some(selector) = &args.measure {
+        let measurement: Option<Measurement> = evaluate(
+            page,
+            &format!(
+                r#"(() => {{
+                    const selector = {};
+                    const element = document.querySelector(selector);
+                    if (!element) return null;
+                    const rect = element.getBoundingClientRect();
+                    const style = getComputedStyle(element);
+                    const visible = rect.width > 0 && rect.height > 0 && style.display !== 'none'
+                        && style.visibility !== 'hidden' && parseFloat(style.opacity) > 0;
+                    const [width, height] = [window.innerWidth, window.innerHeight];
+                    return {{
+                        selector,
+                        x: rect.left + window.scrollX,
+                        y: rect.top + window.scrollY,
+                        width: rect.width,
+                        height: rect.height,
+                        visible,
+                        in_viewport: rect.right > 0 && rect.bottom > 0 && rect.left < width && rect.top < height,
+                        fully_in_viewport: rect.left >= 0 && rect.top >= 0 && rect.right <= width && rect.bottom <= height,
+                    }};
+                }})()"#,
+                serde_json::to_string(selector).map_err(|e| e.to_string())?
+            ),
+        )
+        .await?;
+
+        result.measure = Some(measurement.ok_or_else(|| format!("Measure selector '{}' matched no element", selector))?);
+        if args.measure_only {
+            return Ok(Vec::new());
+        }
+    }
+
+    if let Some(interval) = args.capture_interval {
+        return capture_timelapse(page, args, interval, result).await;
+    }
+
+    if !args.goto.is_empty() || !args.navigation_type.is_empty() {
+        return capture_journey(page, args, result).await;
+    }
+
+    if let Some(count) = args.monkey {
+        return monkey::run(page, args, count, result).await;
+    }
+
+    if args.both_orientations {
+        return capture_orientations(page, args, result).await;
+    }
+
+    if let Some(path) = &args.before_after_gif {
+        return before_after::run(page, args, path, result).await;
+    }
+
+    if args.offline_first {
+        return capture_network_states(page, args, result).await;
+    }
+
+    if let Some(path) = &args.script {
+        return scenario::run(page, args, path, result).await;
+    }
+
+    capture_current(page, args, result).await
+}
+
+/// Stop animations right before the capture, returning the mechanisms applied.
+///
+/// The stylesheet goes first so CSS animations end in their final state, then the engine level
+/// freeze holds whatever JavaScript drives.
+async fn disable_animations(page: &Page, args: &Args) -> Result<Vec<String>, String> {
+    let mut mechanisms = Vec::new();
+
+    if args.freeze_animations {
+        evaluate::<bool>(
+            page,
+            r#"(() => {
+                const style = document.createElement('style');
+                style.textContent = '*, *::before, *::after { animation: none !important; transition: none !important; caret-color: transparent !important; }';
+                (document.head || document.documentElement).appendChild(style);
+                return true;
+            })()"#,
+        )
+        .await?;
+        mechanisms.push("css".to_string());
+    }
+
+    if args.disable_animations_via_cdp {
+        page.execute(AnimationEnableParams::default())
+            .await
+            .map_err(|e| format!("Failed to enable animation domain: {}", e))?;
+        page.execute(SetPlaybackRateParams::new(0.0))
+            .await
+            .map_err(|e| format!("Failed to pause animations: {}", e))?;
+        mechanisms.push("cdp-playback-rate".to_string());
+
+        evaluate::<bool>(
+            page,
+            r#"(() => {
+                document.getAnimations().forEach(animation => animation.pause());
+                window.requestAnimationFrame = () => 0;
+                return true;
+            })()"#,
+        )
+        .await?;
+        mechanisms.push("web-animations-paused".to_string());
+        mechanisms.push("request-animation-frame-stopped".to_string());
+    }
+
+    Ok(mechanisms)
+}
+
+/// Read the computed `properties` of the first element matching `selector`, every property for `all`.
+async fn computed_style(page: &Page, selector: &str, properties: &[String]) -> Result<ComputedStyle, String> {
+    let properties = if properties.is_empty() {
+        COMPUTED_STYLE_PROPERTIES.iter().map(|p| p.to_string()).collect()
+    } else {
+        properties.to_vec()
+    };
+    let all = properties.iter().any(|p| p == "all");
+
+    let values: Option<std::collections::BTreeMap<String, String>> = evaluate(
+        page,
+        &format!(
+            r#"(() => {{
+                const element = document.querySelector({});
+                if (!element) return null;
+                const style = getComputedStyle(element);
+                const names = {} ? Array.from(style) : {};
+                return Object.fromEntries(names.map(name => [name, style.getPropertyValue(name)]));
+            }})()"#,
+            serde_json::to_string(selector).map_err(|e| e.to_string())?,
+            all,
+            serde_json::to_string(&properties).map_err(|e| e.to_string())?,
+        ),
+    )
+    .await?;
+
+    Ok(ComputedStyle {
+        selector: selector.to_string(),
+        properties: values.ok_or_else(|| format!("Selector '{}' matched no element", selector))?,
+    })
+}
+
+/// Identify the interstitial Chrome shows after a failed navigation, if it shows one.
+async fn detect_interstitial(page: &Page, error: &str) -> Option<Interstitial> {
+    // interstitials render their message into #main-message and tag the body with their kind
+    let body_class: Option<String> = evaluate(
+        page,
+        "document.querySelector('#main-message') ? document.body.className : null",
+    )
+    .await
+    .ok()?;
+
+    let body_class = body_class?;
+    let kind = ["safe-browsing", "captive-portal", "ssl"]
+        .into_iter()
+        .find(|kind| body_class.split_whitespace().any(|class| class == *kind));
+
+    // plain network error pages share the markup but are not security interstitials
+    let kind = match kind {
+        Some(kind) => kind,
+        None if error.contains("CERT") || error.contains("SSL") => "unknown",
+        None => return None,
+    };
+
+    Some(Interstitial {
+        kind: kind.to_string(),
+        error: error.to_string(),
+    })
+}
+
+/// Poll `document.readyState` until it reaches `target`, `complete` also satisfying `interactive`.
+async fn wait_ready_state(page: &Page, target: &str, timeout: Duration) -> Result<ReadyState, String> {
+    let started = tokio::time::Instant::now();
+
+    loop {
+        let observed: String = evaluate(page, "document.readyState").await?;
+        if observed == target || observed == "complete" {
+            return Ok(ReadyState {
+                target: target.to_string(),
+                observed,
+                waited_ms: started.elapsed().as_millis() as u64,
+            });
+        }
+
+        if started.elapsed() >= timeout {
+            return Err(format!(
+                "Timed out waiting for document.readyState '{}', last observed '{}'",
+                target, observed
+            ));
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Poll until an element matching `selector` is rendered, returning how long that took.
+async fn wait_for_visible(page: &Page, selector: &str, timeout: Duration) -> Result<u64, String> {
+    let started = tokio::time::Instant::now();
+    let expression = format!(
+        r#"Array.from(document.querySelectorAll({})).some(element => {{
+            const rect = element.getBoundingClientRect();
+            return rect.width > 0 && rect.height > 0 && getComputedStyle(element).visibility !== 'hidden';
+        }})"#,
+        serde_json::to_string(selector).map_err(|e| e.to_string())?
+    );
+
+    loop {
+        let visible: bool = evaluate(page, &expression).await?;
+        if visible {
+            return Ok(started.elapsed().as_millis() as u64);
+        }
+
+        if started.elapsed() >= timeout {
+            return Err(format!("Timed out waiting for '{}' to become visible", selector));
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Poll until at least `threshold` elements match `selector`, giving up after `timeout`.
+async fn wait_for_count(page: &Page, selector: &str, threshold: usize, timeout: Duration) -> Result<ElementCount, String> {
+    let started = tokio::time::Instant::now();
+    let selector_json = serde_json::to_string(selector).map_err(|e| e.to_string())?;
+    let expression = format!("document.querySelectorAll({}).length", selector_json);
+
+    loop {
+        let count: usize = evaluate(page, &expression).await?;
+        let reached = count >= threshold;
+
+        if reached || started.elapsed() >= timeout {
+            return Ok(ElementCount {
+                selector: selector.to_string(),
+                threshold,
+                count,
+                reached,
+                waited_ms: started.elapsed().as_millis() as u64,
+            });
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Navigate to the target of the page's meta refresh right away instead of waiting on its timer.
+async fn follow_meta_refresh(page: &Page) -> Result<MetaRefresh, String> {
+    let target: Option<String> = evaluate(
+        page,
+        r#"(() => {
+            const meta = document.querySelector('meta[http-equiv="refresh" i]');
+            if (!meta) return null;
+            const match = (meta.getAttribute('content') || '').match(/[;,]\s*(?:url\s*=\s*)?['"]?([^'"]+)/i);
+            return match ? new URL(match[1].trim(), location.href).href : location.href;
+        })()"#,
+    )
+    .await?;
+
+    let mut meta_refresh = MetaRefresh {
+        mode: "follow".to_string(),
+        ..Default::default()
+    };
+
+    if let Some(target) = target {
+        page.goto(target.as_str())
+            .await
+            .map_err(|e| format!("Failed to follow meta refresh to '{}': {}", target, e))?;
+        page.wait_for_navigation()
+            .await
+            .map_err(|e| format!("Failed to wait for meta refresh navigation: {}", e))?;
+
+        meta_refresh.detected = true;
+        meta_refresh.followed = true;
+        meta_refresh.target = Some(target);
+    }
+
+    Ok(meta_refresh)
+}
+
+/// Capture each selector listed in `path` as an element screenshot next to `args.output`.
+///
+/// A selector that fails is reported in its entry without stopping the others.
+async fn capture_selector_list(page: &Page, args: &Args, path: &str) -> Result<Vec<SelectorCapture>, String> {
+    let selectors = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read selector list '{}': {}", path, e))?;
+    let mut captures = Vec::new();
+
+    for (index, selector) in selectors
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .enumerate()
+    {
+        let name: String = selector
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .take(60)
+            .collect();
+        let file_path = suffixed_path(&args.output, &format!("-{:03}-{}", index + 1, name.trim_matches('_')));
+        let mut capture = SelectorCapture {
+            selector: selector.to_string(),
+            success: false,
+            size: 0,
+            file_path: None,
+            error: None,
+        };
+
+        let screenshot_data = match page.find_element(selector).await {
+            Ok(element) => element
+                .screenshot(screenshot_format(args))
+                .await
+                .map_err(|e| format!("Failed to capture element screenshot: {}", e)),
+            Err(e) => Err(format!("Selector matched no element: {}", e)),
+        };
+
+        match screenshot_data.and_then(|data| postprocess::encode_side_capture(args, data)).and_then(|data| {
+            fs::write(&file_path, &data)
+                .map(|_| data.len())
+                .map_err(|e| format!("Failed to save element screenshot: {}", e))
+        }) {
+            Ok(size) => {
+                capture.success = true;
+                capture.size = size;
+                capture.file_path = Some(file_path);
+            }
+            Err(e) => capture.error = Some(e),
+        }
+
+        captures.push(capture);
+    }
+
+    Ok(captures)
+}
+
+/// Capture the page, then navigate it through every `--goto` URL and `--navigation-type`, capturing
+/// after each step.
+///
+/// The page is reused so cookies, session state and history carry over. The last capture is returned.
+async fn capture_journey(page: &Page, args: &Args, result: &mut ScreenshotResult) -> Result<Vec<u8>, String> {
+    let mut screenshot_data = capture_current(page, args, result).await?;
+    record_journey_step(page, args, args.url(), None, &screenshot_data, result).await?;
+
+    for url in &args.goto {
+        page.goto(url.as_str())
+            .await
+            .map_err(|e| format!("Failed to navigate to '{}': {}", url, e))?;
+        page.wait_for_navigation()
+            .await
+            .map_err(|e| format!("Failed to wait for navigation to '{}': {}", url, e))?;
+        tokio::time::sleep(args.delay).await;
+
+        screenshot_data = capture_current(page, args, result).await?;
+        record_journey_step(page, args, url, None, &screenshot_data, result).await?;
+    }
+
+    for navigation_type in &args.navigation_type {
+        let url = navigate_history(page, navigation_type).await?;
+        page.wait_for_navigation()
+            .await
+            .map_err(|e| format!("Failed to wait for {} navigation: {}", navigation_type, e))?;
+        tokio::time::sleep(args.delay).await;
+
+        screenshot_data = capture_current(page, args, result).await?;
+        record_journey_step(page, args, &url, Some(navigation_type.as_str()), &screenshot_data, result).await?;
+    }
+
+    Ok(screenshot_data)
+}
+
+/// Capture the page in portrait and then in landscape, resizing the viewport in between.
+///
+/// The landscape capture is returned.
+async fn capture_orientations(page: &Page, args: &Args, result: &mut ScreenshotResult) -> Result<Vec<u8>, String> {
+    let (short, long) = (args.width.min(args.height), args.width.max(args.height));
+    let mut captures = Vec::with_capacity(2);
+    let mut screenshot_data = Vec::new();
+
+    for (orientation, width, height, kind, angle) in [
+        ("portrait", short, long, ScreenOrientationType::PortraitPrimary, 0),
+        ("landscape", long, short, ScreenOrientationType::LandscapePrimary, 90),
+    ] {
+        page.execute(
+            SetDeviceMetricsOverrideParams::builder()
+                .width(width as i64)
+                .height(height as i64)
+                .device_scale_factor(applied_scale(result))
+                // mobile like set_viewport, so a viewport meta override keeps applying
+                .mobile(args.viewport_meta_override.is_some())
+                .screen_orientation(ScreenOrientation::new(kind, angle))
+                .build()?,
+        )
+        .await
+        .map_err(|e| format!("Failed to switch to {} {}x{}: {}", orientation, width, height, e))?;
+
+        // let resize and orientationchange handlers re-layout the page
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        screenshot_data = capture_current(page, args, result).await?;
+        let file_path = suffixed_path(&args.output, &format!("-{}", orientation));
+        let encoded = postprocess::encode_side_capture(args, screenshot_data.clone())?;
+        fs::write(&file_path, &encoded)
+            .map_err(|e| format!("Failed to save {} capture: {}", orientation, e))?;
+
+        captures.push(OrientationCapture {
+            orientation: orientation.to_string(),
+            width,
+            height,
+            file_path,
+            size: encoded.len(),
+        });
+    }
+
+    result.width = long;
+    result.height = short;
+    result.orientations = Some(captures);
+    Ok(screenshot_data)
+}
+
+/// Reload the page or move through its history, returning the url navigated to.
+async fn navigate_history(page: &Page, navigation_type: &str) -> Result<String, String> {
+    if navigation_type == "reload" {
+        let url = page.url().await.ok().flatten().unwrap_or_default();
+        page.execute(ReloadParams::default())
+            .await
+            .map_err(|e| format!("Failed to reload: {}", e))?;
+        return Ok(url);
+    }
+
+    let history = page
+        .execute(GetNavigationHistoryParams::default())
+        .await
+        .map_err(|e| format!("Failed to get navigation history: {}", e))?;
+    let offset = if navigation_type == "back" { -1 } else { 1 };
+    let entry = usize::try_from(history.result.current_index + offset)
+        .ok()
+        .and_then(|index| history.result.entries.get(index))
+        .ok_or_else(|| format!("Cannot go {}, no history entry there", navigation_type))?;
+
+    page.execute(NavigateToHistoryEntryParams::new(entry.id))
+        .await
+        .map_err(|e| format!("Failed to go {}: {}", navigation_type, e))?;
+
+    Ok(entry.url.clone())
+}
+
+/// Take the loaded page offline and reload it, capturing what the service worker or cache serves,
+/// then optionally back online. The last capture is returned.
+async fn capture_network_states(page: &Page, args: &Args, result: &mut ScreenshotResult) -> Result<Vec<u8>, String> {
+    let states: &[(&str, bool)] = if args.offline_restore {
+        &[("offline", true), ("online", false)]
+    } else {
+        &[("offline", true)]
+    };
+    let mut captures = Vec::with_capacity(states.len());
+    let mut screenshot_data = Vec::new();
+
+    for &(state, offline) in states {
+        page.execute(EmulateNetworkConditionsParams::new(offline, 0.0, -1.0, -1.0))
+            .await
+            .map_err(|e| format!("Failed to go {}: {}", state, e))?;
+        page.execute(ReloadParams::default())
+            .await
+            .map_err(|e| format!("Failed to reload {}: {}", state, e))?;
+        page.wait_for_navigation()
+            .await
+            .map_err(|e| format!("Failed to wait for the {} reload: {}", state, e))?;
+        tokio::time::sleep(args.delay).await;
+
+        screenshot_data = capture_current(page, args, result).await?;
+        let file_path = suffixed_path(&args.output, &format!("-{}", state));
+        let encoded = postprocess::encode_side_capture(args, screenshot_data.clone())?;
+        fs::write(&file_path, &encoded).map_err(|e| format!("Failed to save {} capture: {}", state, e))?;
+
+        captures.push(NetworkStateCapture {
+            state: state.to_string(),
+            final_url: page.url().await.ok().flatten(),
+            file_path,
+            size: encoded.len(),
+        });
+    }
+
+    result.network_states = Some(captures);
+    Ok(screenshot_data)
+}
+
+/// Save the capture of a journey step to its numbered file and add it to the result.
+async fn record_journey_step(
+    page: &Page,
+    args: &Args,
+    url: &str,
+    navigation_type: Option<&str>,
+    screenshot_data: &[u8],
+    result: &mut ScreenshotResult,
+) -> Result<(), String> {
+    let steps = result.journey.get_or_insert_with(Vec::new);
+    let step = steps.len() + 1;
+    let file_path = suffixed_path(&args.output, &format!("-step-{:02}", step));
+
+    let encoded = postprocess::encode_side_capture(args, screenshot_data.to_vec())?;
+    fs::write(&file_path, &encoded)
+        .map_err(|e| format!("Failed to save journey step {}: {}", step, e))?;
+
+    steps.push(JourneyStep {
+        step,
+        url: url.to_string(),
+        final_url: page.url().await.ok().flatten(),
+        navigation_type: navigation_type.map(String::from),
+        file_path,
+        size: encoded.len(),
+    });
+
+    Ok(())
+}
+
+/// Capture the page every `interval` for `args.capture_duration`, writing numbered files.
+///
+/// The bytes of the last frame are returned so `--output` holds the latest state.
+async fn capture_timelapse(page: &Page, args: &Args, interval: Duration, result: &mut ScreenshotResult) -> Result<Vec<u8>, String> {
+    let started = tokio::time::Instant::now();
+    let mut frames = Vec::new();
+
+    loop {
+        if args.reload_each && !frames.is_empty() {
+            page.reload()
+                .await
+                .map_err(|e| format!("Failed to reload page: {}", e))?;
+        }
+
+        let screenshot_data = capture_current(page, args, result).await?;
+        let index = frames.len() + 1;
+        let file_path = numbered_path(&args.output, index);
+
+        fs::write(&file_path, postprocess::encode_side_capture(args, screenshot_data.clone())?)
+            .map_err(|e| format!("Failed to save time-lapse frame {}: {}", index, e))?;
+
+        frames.push(TimelapseFrame {
+            index,
+            file_path,
+            captured_at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        });
+
+        let next = interval * index as u32;
+        if next > args.capture_duration {
+            result.timelapse = Some(frames);
+            return Ok(screenshot_data);
+        }
+
+        tokio::time::sleep_until(started + next).await;
+    }
+}
+
+/// `shot.png` to `shot-003.png` for the frame at `index`.
+fn numbered_path(output: &str, index: usize) -> String {
+    suffixed_path(output, &format!("-{:03}", index))
+}
+
+/// A sibling of the output file with `suffix` appended to its stem, `shot.png` to `shot{suffix}.png`.
+fn suffixed_path(output: &str, suffix: &str) -> String {
+    let path = Path::new(output);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("screenshot");
+    let file_name = match path.extension().and_then(|s| s.to_str()) {
+        Some(extension) => format!("{}{}.{}", stem, suffix, extension),
+        None => format!("{}{}", stem, suffix),
+    };
+
+    path.with_file_name(file_name).to_string_lossy().into_owned()
+}
+
+/// Capture the current state of the page, or of the selected element.
+async fn capture_current(page: &Page, args: &Args, result: &mut ScreenshotResult) -> Result<Vec<u8>, String> {
+    // Grab the OS window with its browser chrome, always as PNG
+    if args.with_browser_chrome {
+        let (screenshot_data, window_capture) = window::capture_window(page).await?;
+        result.format = "png".to_string();
+        result.window_capture = Some(window_capture);
+        return Ok(screenshot_data);
+    }
+
+    if args.format == "pdf" {
+        return pdf::capture(page, args, result).await;
+    }
+
+    // Capture a single element when a selector was given
+    if let Some(selector) = &args.selector {
+        let elements = page
+            .find_elements(selector.as_str())
+            .await
+            .map_err(|e| format!("Failed to find elements for selector '{}': {}", selector, e))?;
+        let index = args.element_index.unwrap_or(0);
+
+        result.match_count = Some(elements.len());
+
+        let element = elements.get(index).ok_or_else(|| {
+            format!(
+                "Element index {} is out of range, selector '{}' matched {} element(s)",
+                index,
+                selector,
+                elements.len()
+            )
+        })?;
+
+        result.element_index = Some(index);
+
+        return element
+            .screenshot(screenshot_format(args))
+            .await
+            .map_err(|e| format!("Failed to capture element screenshot: {}", e));
+    }
+
+    // Clip the page screenshot to an element without dropping what overlaps it
+    if let Some(selector) = &args.clip_selector {
+        let clip = element_rect(page, selector)
+            .await?
+            .filter(|rect| rect.width > 0.0 && rect.height > 0.0)
+            .ok_or_else(|| format!("Clip selector '{}' matched no visible element", selector))?;
+
+        result.clip = Some(clip);
+        return capture_page(page, args, Some(clip)).await;
+    }
+
+    // Clip to the section between two anchor elements
+    if let (Some(from), Some(to)) = (&args.clip_from, &args.clip_to) {
+        let start = element_rect(page, from)
+            .await?
+            .ok_or_else(|| format!("Clip anchor '{}' matched no element", from))?;
+        let end = element_rect(page, to)
+            .await?
+            .ok_or_else(|| format!("Clip anchor '{}' matched no element", to))?;
+        let bottom = end.y + end.height;
+
+        if bottom <= start.y {
+            return Err(format!(
+                "Clip anchors are out of order, '{}' ends at {} above the top of '{}' at {}",
+                to, bottom, from, start.y
+            ));
+        }
+
+        let left = start.x.min(end.x);
+        let right = (start.x + start.width).max(end.x + end.width);
+        let clip = Rect {
+            x: left,
+            y: start.y,
+            width: right - left,
+            height: bottom - start.y,
+        };
+
+        result.clip = Some(clip);
+        return capture_page(page, args, Some(clip)).await;
+    }
+
+    if let (true, Some(limit)) = (args.full_page, args.max_capture_dimension) {
+        let (width, height): (u32, u32) = evaluate(
+            page,
+            "[document.documentElement.scrollWidth, Math.max(document.documentElement.scrollHeight, document.body ? document.body.scrollHeight : 0)]",
+        )
+        .await?;
+
+        let scale = applied_scale(result);
+        if width as f64 * scale > limit as f64 || height as f64 * scale > limit as f64 {
+            let (screenshot_data, fallback) =
+                tiles::capture(page, args, width, height, limit, result).await?;
+            result.capture_fallback = Some(fallback);
+            return Ok(screenshot_data);
+        }
+    }
+
+    capture_page(page, args, None).await
+}
+
+/// Take a page screenshot, optionally clipped to a document relative rectangle.
+async fn capture_page(page: &Page, args: &Args, clip: Option<Rect>) -> Result<Vec<u8>, String> {
+    // Take screenshot
+    let mut screenshot_params = CaptureScreenshotParams::builder()
+        .format(screenshot_format(args))
+        .quality(match args.format.as_str() {
+            // keep the source close to lossless when it is re-encoded afterwards
+            "jpeg" | "jpg" if args.reencodes_jpeg() => 100,
+            "jpeg" | "jpg" | "webp" => args.quality as i64,
+            _ => 90,
+        })
+        .capture_beyond_viewport(args.full_page || clip.is_some());
+
+    if let Some(clip) = clip {
+        screenshot_params = screenshot_params.clip(Viewport {
+            x: clip.x,
+            y: clip.y,
+            width: clip.width,
+            height: clip.height,
+            scale: 1.0,
+        });
+    }
+
+    page.screenshot(screenshot_params.build())
+        .await
+        .map_err(|e| format!("Failed to capture screenshot: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_durations_out_of_range() {
+        assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+        assert!(parse_duration("1e400").is_err());
+        assert!(parse_duration("99999999999999999999999h").is_err());
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("0.0001ms").is_ok());
+    }
+
+    /// `Sun, 06 Nov 1994 08:49:37 GMT`
+    const DATE: u64 = 784_111_777;
+
+    fn response(status: i64, retry_after: Option<&str>) -> network::DocumentResponse {
+        network::DocumentResponse {
+            url: "https://example.com/".to_string(),
+            status,
+            content_type: None,
+            retry_after: retry_after.map(String::from),
+        }
+    }
+
+    #[test]
+    fn retry_after_takes_delay_seconds() {
+        assert_eq!(parse_retry_after("5", DATE), Some(Duration::from_secs(5)));
+        assert_eq!(parse_retry_after(" 0 ", DATE), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn retry_after_takes_an_http_date() {
+        let value = "Sun, 06 Nov 1994 08:49:37 GMT";
+        assert_eq!(parse_retry_after(value, DATE - 12), Some(Duration::from_secs(12)));
+    }
+
+    #[test]
+    fn retry_after_in_the_past_is_immediate() {
+        let value = "Sun, 06 Nov 1994 08:49:37 GMT";
+        assert_eq!(parse_retry_after(value, DATE + 60), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn retry_after_is_capped_at_30_seconds() {
+        assert_eq!(parse_retry_after("3600", DATE), Some(MAX_RETRY_DELAY));
+        let value = "Sun, 06 Nov 1994 08:49:37 GMT";
+        assert_eq!(parse_retry_after(value, DATE - 3600), Some(MAX_RETRY_DELAY));
+    }
+
+    #[test]
+    fn retry_after_rejects_garbage() {
+        assert_eq!(parse_retry_after("soon", DATE), None);
+        assert_eq!(parse_retry_after("-5", DATE), None);
+    }
+
+    #[test]
+    fn scale_factor_within_budget_is_kept() {
+        let scale = clamp_scale_factor(2.0, 1920, 1080, 67_108_864);
+        assert_eq!(scale.applied, 2.0);
+        assert!(!scale.clamped);
+        assert_eq!((scale.output_width, scale.output_height), (3840, 2160));
+    }
+
+    #[test]
+    fn scale_factor_is_clamped_to_the_pixel_budget_and_side() {
+        let scale = clamp_scale_factor(4.0, 1000, 1000, 4_000_000);
+        assert_eq!(scale.applied, 2.0);
+        assert!(scale.clamped);
+
+        // 16384 pixels a side at most, however large the budget
+        let scale = clamp_scale_factor(10.0, 4096, 100, u64::MAX);
+        assert_eq!(scale.applied, 4.0);
+        assert_eq!(scale.output_width, 16384);
+    }
+
+    #[test]
+    fn zero_and_nan_scale_factors_stay_usable() {
+        let scale = clamp_scale_factor(0.0, 1920, 1080, 67_108_864);
+        assert_eq!(scale.applied, 0.1);
+        assert!(scale.clamped);
+
+        let scale = clamp_scale_factor(f64::NAN, 1920, 1080, 67_108_864);
+        assert_eq!(scale.applied, 1.0);
+        assert!(scale.clamped);
+
+        let scale = clamp_scale_factor(1.0, 0, 0, 67_108_864);
+        assert_eq!(scale.applied, 1.0);
+    }
+
+    #[test]
+    fn only_listed_statuses_honor_retry_after() {
+        let listed = [429, 503];
+        assert_eq!(
+            status_retry_after(&listed, Some(&response(429, Some("7")))),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(status_retry_after(&listed, Some(&response(500, Some("7")))), None);
+        assert_eq!(status_retry_after(&listed, Some(&response(503, None))), None);
+        assert_eq!(status_retry_after(&listed, None), None);
+    }
+}
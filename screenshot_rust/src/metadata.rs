@@ -0,0 +1,219 @@
+// Provenance metadata embedded into the image file itself:
+// PNG gets `tEXt` chunks after `IHDR`, JPEG gets an EXIF `APP1` segment after `SOI` / `APP0`.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where and how a capture was made.
+pub struct Provenance {
+    pub url: String,
+    pub viewport: String,
+    pub software: String,
+    /// seconds since the unix epoch.
+    pub captured_at: u64,
+}
+
+impl Provenance {
+    pub fn now(url: &str, width: u32, height: u32) -> Self {
+        Provenance {
+            url: url.to_string(),
+            viewport: format!("{}x{}", width, height),
+            software: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            captured_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+}
+
+/// Embed the provenance into a PNG or JPEG, returning the names of the fields written.
+pub fn embed(data: &[u8], provenance: &Provenance) -> Result<(Vec<u8>, Vec<String>), String> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        embed_png(data, provenance)
+    } else if data.starts_with(&[0xff, 0xd8]) {
+        embed_jpeg(data, provenance)
+    } else {
+        Err("Embedding metadata is only supported for PNG and JPEG".to_string())
+    }
+}
+
+fn embed_png(data: &[u8], provenance: &Provenance) -> Result<(Vec<u8>, Vec<String>), String> {
+    // signature (8) + IHDR length, type, 13 bytes of data and crc
+    const IHDR_END: usize = 8 + 4 + 4 + 13 + 4;
+
+    if data.len() < IHDR_END || &data[12..16] != b"IHDR" {
+        return Err("Malformed PNG, IHDR chunk not found".to_string());
+    }
+
+    let fields = [
+        ("URL", provenance.url.clone()),
+        ("Creation Time", rfc3339(provenance.captured_at)),
+        ("Viewport", provenance.viewport.clone()),
+        ("Software", provenance.software.clone()),
+    ];
+
+    let mut embedded = Vec::with_capacity(data.len() + 512);
+    embedded.extend_from_slice(&data[..IHDR_END]);
+
+    for (keyword, text) in &fields {
+        let mut chunk = Vec::with_capacity(4 + keyword.len() + 1 + text.len());
+        chunk.extend_from_slice(b"tEXt");
+        chunk.extend_from_slice(keyword.as_bytes());
+        chunk.push(0);
+        // tEXt is latin-1, keep the text to plain ascii
+        chunk.extend(text.bytes().map(|b| if b.is_ascii() { b } else { b'?' }));
+
+        embedded.extend_from_slice(&((chunk.len() - 4) as u32).to_be_bytes());
+        embedded.extend_from_slice(&chunk);
+        embedded.extend_from_slice(&crc32fast::hash(&chunk).to_be_bytes());
+    }
+
+    embedded.extend_from_slice(&data[IHDR_END..]);
+
+    Ok((embedded, fields.iter().map(|(keyword, _)| keyword.to_string()).collect()))
+}
+
+fn embed_jpeg(data: &[u8], provenance: &Provenance) -> Result<(Vec<u8>, Vec<String>), String> {
+    // ascii TIFF tags, sorted by tag number as the IFD requires
+    let fields: [(u16, &str, String); 4] = [
+        (0x010d, "DocumentName", provenance.url.clone()),
+        (0x010e, "ImageDescription", format!("viewport {}", provenance.viewport)),
+        (0x0131, "Software", provenance.software.clone()),
+        (0x0132, "DateTime", exif_datetime(provenance.captured_at)),
+    ];
+
+    let data_start = 8 + 2 + fields.len() * 12 + 4;
+    let mut ifd = Vec::new();
+    let mut values = Vec::new();
+
+    ifd.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+    for (tag, _, value) in &fields {
+        let mut value = value.bytes().filter(u8::is_ascii).take(1024).collect::<Vec<u8>>();
+        value.push(0);
+
+        ifd.extend_from_slice(&tag.to_be_bytes());
+        ifd.extend_from_slice(&2u16.to_be_bytes());
+        ifd.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        ifd.extend_from_slice(&((data_start + values.len()) as u32).to_be_bytes());
+        values.extend_from_slice(&value);
+    }
+    ifd.extend_from_slice(&0u32.to_be_bytes());
+
+    let mut exif = b"Exif\0\0MM\0\x2a\0\0\0\x08".to_vec();
+    exif.extend_from_slice(&ifd);
+    exif.extend_from_slice(&values);
+
+    // the APP1 goes after SOI, or after a JFIF APP0 when there is one
+    let mut insert_at = 2;
+    if data.len() > 6 && data[2..4] == [0xff, 0xe0] {
+        insert_at = 4 + u16::from_be_bytes([data[4], data[5]]) as usize;
+    }
+
+    if insert_at > data.len() {
+        return Err("Malformed JPEG, APP0 segment is truncated".to_string());
+    }
+
+    let mut embedded = Vec::with_capacity(data.len() + exif.len() + 4);
+    embedded.extend_from_slice(&data[..insert_at]);
+    embedded.extend_from_slice(&[0xff, 0xe1]);
+    embedded.extend_from_slice(&((exif.len() + 2) as u16).to_be_bytes());
+    embedded.extend_from_slice(&exif);
+    embedded.extend_from_slice(&data[insert_at..]);
+
+    Ok((embedded, fields.iter().map(|(_, name, _)| name.to_string()).collect()))
+}
+
+/// `2024-01-31T12:00:00Z`
+pub fn rfc3339(seconds: u64) -> String {
+    let (year, month, day, hour, minute, second) = utc(seconds);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, hour, minute, second)
+}
+
+/// `2024:01:31 12:00:00`
+fn exif_datetime(seconds: u64) -> String {
+    let (year, month, day, hour, minute, second) = utc(seconds);
+    format!("{:04}:{:02}:{:02} {:02}:{:02}:{:02}", year, month, day, hour, minute, second)
+}
+
+/// Split unix seconds into a UTC calendar date and time.
+fn utc(seconds: u64) -> (i64, u64, u64, u64, u64, u64) {
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+
+    // civil from days, proleptic gregorian calendar
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u64;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u64;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provenance() -> Provenance {
+        Provenance {
+            url: "https://example.com/".to_string(),
+            viewport: "1920x1080".to_string(),
+            software: "screenshot_rust 0.1.0".to_string(),
+            captured_at: 784_111_777,
+        }
+    }
+
+    fn encode(format: image::ImageFormat) -> Vec<u8> {
+        let mut data = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(4, 4).write_to(&mut data, format).unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn embeds_png_text_chunks_after_ihdr() {
+        let (embedded, fields) = embed(&encode(image::ImageFormat::Png), &provenance()).unwrap();
+        assert_eq!(fields, ["URL", "Creation Time", "Viewport", "Software"]);
+        assert_eq!(&embedded[37..41], b"tEXt");
+
+        let reader = png::Decoder::new(embedded.as_slice()).read_info().unwrap();
+        let text = reader
+            .info()
+            .uncompressed_latin1_text
+            .iter()
+            .map(|chunk| (chunk.keyword.as_str(), chunk.text.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(
+            text,
+            [
+                ("URL", "https://example.com/"),
+                ("Creation Time", "1994-11-06T08:49:37Z"),
+                ("Viewport", "1920x1080"),
+                ("Software", "screenshot_rust 0.1.0"),
+            ]
+        );
+    }
+
+    #[test]
+    fn embeds_jpeg_exif_after_app0() {
+        let data = encode(image::ImageFormat::Jpeg);
+        let (embedded, fields) = embed(&data, &provenance()).unwrap();
+        assert_eq!(fields, ["DocumentName", "ImageDescription", "Software", "DateTime"]);
+
+        let app1 = 4 + u16::from_be_bytes([data[4], data[5]]) as usize;
+        assert_eq!(&embedded[..app1], &data[..app1]);
+        assert_eq!(&embedded[app1..app1 + 2], &[0xff, 0xe1]);
+        assert_eq!(&embedded[app1 + 4..app1 + 10], b"Exif\0\0");
+        assert!(embedded.windows(19).any(|window| window == b"1994:11:06 08:49:37"));
+        assert!(image::load_from_memory(&embedded).is_ok());
+    }
+
+    #[test]
+    fn refuses_other_formats() {
+        assert!(embed(b"RIFF\0\0\0\0WEBP", &provenance()).is_err());
+        assert!(embed(b"\x89PNG\r\n\x1a\n", &provenance()).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+// `--monkey`: a lightweight visual fuzzer clicking and scrolling at random, capturing after each action.
+
+use crate::{capture_current, evaluate, postprocess, suffixed_path, Args, ScreenshotResult};
+use chromiumoxide::layout::Point;
+use chromiumoxide::Page;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::Duration;
+
+/// How long the page gets to react to an action before it is captured.
+const SETTLE: Duration = Duration::from_millis(500);
+
+/// The random interactions performed and the capture taken after each.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Monkey {
+    /// replays the same sequence on the same page.
+    pub seed: u64,
+    pub actions: Vec<MonkeyAction>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct MonkeyAction {
+    pub step: usize,
+    /// `click` or `scroll`.
+    pub action: String,
+    /// the element clicked, e.g. `button#submit`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub x: f64,
+    pub y: f64,
+    pub file_path: String,
+    pub size: usize,
+}
+
+/// A visible interactive element, at its center in viewport coordinates.
+#[derive(Deserialize)]
+struct Candidate {
+    description: String,
+    x: f64,
+    y: f64,
+}
+
+const CANDIDATES: &str = r#"(() => {
+    const selector = 'a[href], button, input, select, textarea, summary, [role="button"], [onclick], [tabindex]';
+    return Array.from(document.querySelectorAll(selector)).flatMap(el => {
+        const rect = el.getBoundingClientRect();
+        const style = getComputedStyle(el);
+        const visible = rect.width > 0 && rect.height > 0
+            && rect.bottom > 0 && rect.right > 0
+            && rect.top < window.innerHeight && rect.left < window.innerWidth
+            && style.visibility !== 'hidden' && style.pointerEvents !== 'none';
+        if (!visible) return [];
+
+        let description = el.tagName.toLowerCase();
+        if (el.id) description += '#' + el.id;
+        else if (typeof el.className === 'string' && el.className.trim()) {
+            description += '.' + el.className.trim().split(/\s+/).join('.');
+        }
+        return [{ description, x: rect.left + rect.width / 2, y: rect.top + rect.height / 2 }];
+    });
+})()"#;
+
+/// Perform `count` random clicks and scrolls, capturing after each one.
+///
+/// The bytes of the last capture are returned.
+pub async fn run(page: &Page, args: &Args, count: usize, result: &mut ScreenshotResult) -> Result<Vec<u8>, String> {
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut monkey = Monkey {
+        seed,
+        actions: Vec::with_capacity(count),
+    };
+    let mut screenshot_data = Vec::new();
+
+    for step in 1..=count {
+        let candidates: Vec<Candidate> = evaluate(page, CANDIDATES).await?;
+
+        // scroll now and then, and always when there is nothing to click
+        let mut action = if candidates.is_empty() || rng.gen_bool(0.25) {
+            let (x, y) = (0.0, rng.gen_range(-600.0..1200.0_f64).round());
+            evaluate::<bool>(page, &format!("(() => {{ window.scrollBy({}, {}); return true; }})()", x, y)).await?;
+
+            new_action(step, "scroll", None, x, y)
+        } else {
+            let candidate = &candidates[rng.gen_range(0..candidates.len())];
+            page.click(Point::new(candidate.x, candidate.y))
+                .await
+                .map_err(|e| format!("Failed to click '{}': {}", candidate.description, e))?;
+
+            new_action(step, "click", Some(candidate.description.clone()), candidate.x, candidate.y)
+        };
+
+        tokio::time::sleep(SETTLE).await;
+
+        screenshot_data = capture_current(page, args, result).await?;
+        action.file_path = suffixed_path(&args.output, &format!("-monkey-{:03}", step));
+        let encoded = postprocess::encode_side_capture(args, screenshot_data.clone())?;
+        action.size = encoded.len();
+        fs::write(&action.file_path, &encoded)
+            .map_err(|e| format!("Failed to save monkey step {}: {}", step, e))?;
+
+        monkey.actions.push(action);
+    }
+
+    result.monkey = Some(monkey);
+    Ok(screenshot_data)
+}
+
+fn new_action(step: usize, action: &str, target: Option<String>, x: f64, y: f64) -> MonkeyAction {
+    MonkeyAction {
+        step,
+        action: action.to_string(),
+        target,
+        x,
+        y,
+        file_path: String::new(),
+        size: 0,
+    }
+}
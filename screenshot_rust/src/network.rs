@@ -0,0 +1,224 @@
+use chromiumoxide::cdp::browser_protocol::network::{
+    EventLoadingFailed, EventLoadingFinished, EventRequestWillBeSent, EventResponseReceived, ResourceType,
+};
+use chromiumoxide::Page;
+use futures::StreamExt;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// A request that failed to load.
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
+pub struct FailedRequest {
+    pub url: String,
+    pub resource_type: String,
+    pub error: String,
+    pub canceled: bool,
+}
+
+/// A response to a main frame navigation.
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
+pub struct DocumentResponse {
+    pub url: String,
+    pub status: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<String>,
+}
+
+/// Responses to subresource requests, counted by outcome.
+#[derive(Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct AssetStatuses {
+    pub requests: usize,
+    /// responses with a 4xx or 5xx status.
+    pub failed: usize,
+    /// `failed` over `requests`, 0 without requests.
+    pub ratio: f64,
+    pub failed_urls: Vec<String>,
+}
+
+/// Network activity observed on a page.
+#[derive(Default)]
+pub struct NetworkLog {
+    /// request id to requested url.
+    urls: HashMap<String, String>,
+    /// request id with the failure, resolved to a url when reported.
+    failures: Vec<(String, FailedRequest)>,
+}
+
+impl NetworkLog {
+    /// The failed requests with their urls resolved.
+    pub fn failed_requests(&self) -> Vec<FailedRequest> {
+        self.failures
+            .iter()
+            .map(|(request_id, failure)| FailedRequest {
+                url: self.urls.get(request_id).cloned().unwrap_or_default(),
+                ..failure.clone()
+            })
+            .collect()
+    }
+}
+
+/// Record the network events of the page in the background until it closes.
+pub async fn watch(page: &Page) -> Result<Arc<Mutex<NetworkLog>>, String> {
+    let mut requests = page
+        .event_listener::<EventRequestWillBeSent>()
+        .await
+        .map_err(|e| format!("Failed to listen for requests: {}", e))?;
+    let mut failures = page
+        .event_listener::<EventLoadingFailed>()
+        .await
+        .map_err(|e| format!("Failed to listen for failed requests: {}", e))?;
+
+    let log = Arc::new(Mutex::new(NetworkLog::default()));
+    let task_log = log.clone();
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                Some(event) = requests.next() => {
+                    if let Ok(mut log) = task_log.lock() {
+                        log.urls.insert(event.request_id.inner().clone(), event.request.url.clone());
+                    }
+                }
+                Some(event) = failures.next() => {
+                    if let Ok(mut log) = task_log.lock() {
+                        log.failures.push((
+                            event.request_id.inner().clone(),
+                            FailedRequest {
+                                url: String::new(),
+                                resource_type: event.r#type.as_ref().to_string(),
+                                error: event.error_text.clone(),
+                                canceled: event.canceled.unwrap_or_default(),
+                            },
+                        ));
+                    }
+                }
+                else => break,
+            }
+        }
+    });
+
+    Ok(log)
+}
+
+/// Track the ids of requests that have started but not finished or failed yet.
+pub async fn track_in_flight(page: &Page) -> Result<Arc<Mutex<HashSet<String>>>, String> {
+    let mut requests = page
+        .event_listener::<EventRequestWillBeSent>()
+        .await
+        .map_err(|e| format!("Failed to listen for requests: {}", e))?;
+    let mut finished = page
+        .event_listener::<EventLoadingFinished>()
+        .await
+        .map_err(|e| format!("Failed to listen for finished requests: {}", e))?;
+    let mut failures = page
+        .event_listener::<EventLoadingFailed>()
+        .await
+        .map_err(|e| format!("Failed to listen for failed requests: {}", e))?;
+
+    let in_flight = Arc::new(Mutex::new(HashSet::new()));
+    let task_in_flight = in_flight.clone();
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                Some(event) = requests.next() => {
+                    if let Ok(mut in_flight) = task_in_flight.lock() {
+                        in_flight.insert(event.request_id.inner().clone());
+                    }
+                }
+                Some(event) = finished.next() => {
+                    if let Ok(mut in_flight) = task_in_flight.lock() {
+                        in_flight.remove(event.request_id.inner());
+                    }
+                }
+                Some(event) = failures.next() => {
+                    if let Ok(mut in_flight) = task_in_flight.lock() {
+                        in_flight.remove(event.request_id.inner());
+                    }
+                }
+                else => break,
+            }
+        }
+    });
+
+    Ok(in_flight)
+}
+
+/// Record every response to a main frame navigation, in order, until the page closes.
+pub async fn watch_documents(page: &Page) -> Result<Arc<Mutex<Vec<DocumentResponse>>>, String> {
+    let main_frame = page
+        .mainframe()
+        .await
+        .map_err(|e| format!("Failed to get the main frame: {}", e))?;
+    let mut responses = page
+        .event_listener::<EventResponseReceived>()
+        .await
+        .map_err(|e| format!("Failed to listen for responses: {}", e))?;
+
+    let documents = Arc::new(Mutex::new(Vec::new()));
+    let task_documents = documents.clone();
+
+    tokio::spawn(async move {
+        while let Some(event) = responses.next().await {
+            if event.r#type != ResourceType::Document || event.frame_id != main_frame {
+                continue;
+            }
+
+            let header = |name: &str| {
+                event
+                    .response
+                    .headers
+                    .inner()
+                    .as_object()
+                    .and_then(|headers| headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name)))
+                    .and_then(|(_, value)| value.as_str())
+                    .map(String::from)
+            };
+
+            if let Ok(mut documents) = task_documents.lock() {
+                documents.push(DocumentResponse {
+                    url: event.response.url.clone(),
+                    status: event.response.status,
+                    content_type: header("content-type"),
+                    retry_after: header("retry-after"),
+                });
+            }
+        }
+    });
+
+    Ok(documents)
+}
+
+/// Count the status of every response that is not a document, until the page closes.
+pub async fn watch_asset_statuses(page: &Page) -> Result<Arc<Mutex<AssetStatuses>>, String> {
+    let mut responses = page
+        .event_listener::<EventResponseReceived>()
+        .await
+        .map_err(|e| format!("Failed to listen for responses: {}", e))?;
+
+    let statuses = Arc::new(Mutex::new(AssetStatuses::default()));
+    let task_statuses = statuses.clone();
+
+    tokio::spawn(async move {
+        while let Some(event) = responses.next().await {
+            if event.r#type == ResourceType::Document {
+                continue;
+            }
+
+            if let Ok(mut statuses) = task_statuses.lock() {
+                statuses.requests += 1;
+                if event.response.status >= 400 {
+                    statuses.failed += 1;
+                    statuses.failed_urls.push(event.response.url.clone());
+                }
+                statuses.ratio = statuses.failed as f64 / statuses.requests as f64;
+            }
+        }
+    });
+
+    Ok(statuses)
+}
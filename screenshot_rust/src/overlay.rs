@@ -0,0 +1,118 @@
+// `--overlay-metrics`: a small info panel drawn onto the capture so it describes itself in reports.
+
+use crate::placeholder;
+use image::{ImageOutputFormat, Rgba};
+use rusttype::Scale;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// What the panel shows, read from the page before capturing.
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
+pub struct PageMetrics {
+    /// navigation start to the end of the load event.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_ms: Option<f64>,
+    /// subresources fetched by the page.
+    pub resources: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<i64>,
+}
+
+/// The panel drawn onto the image, in image pixels.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Overlay {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub lines: Vec<String>,
+}
+
+const PANEL: [u8; 3] = [0x11, 0x11, 0x11];
+const PANEL_OPACITY: f32 = 0.75;
+const TEXT: Rgba<u8> = Rgba([0xff, 0xff, 0xff, 0xff]);
+
+/// Draw the metrics panel in the bottom left corner, keeping the image's PNG or JPEG encoding.
+pub fn draw(data: &[u8], metrics: &PageMetrics, quality: u8) -> Result<(Vec<u8>, Overlay), String> {
+    let output_format = if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        ImageOutputFormat::Png
+    } else if data.starts_with(&[0xff, 0xd8]) {
+        ImageOutputFormat::Jpeg(quality)
+    } else {
+        return Err("--overlay-metrics is only supported for PNG and JPEG captures".to_string());
+    };
+    let font = placeholder::font().ok_or("--overlay-metrics needs a system font such as DejaVu Sans")?;
+    let mut image = image::load_from_memory(data)
+        .map_err(|e| format!("Failed to decode screenshot: {}", e))?
+        .to_rgba8();
+
+    let lines = vec![
+        format!(
+            "load {}",
+            metrics
+                .load_ms
+                .map(|ms| format!("{:.0} ms", ms))
+                .unwrap_or_else(|| "n/a".to_string())
+        ),
+        format!("{} resources", metrics.resources),
+        format!(
+            "HTTP {}",
+            metrics
+                .status
+                .map(|status| status.to_string())
+                .unwrap_or_else(|| "n/a".to_string())
+        ),
+    ];
+
+    let size = (image.width() as f32 / 80.0).clamp(12.0, 24.0);
+    let padding = (size * 0.6) as u32;
+    // glyphs average about half their height in width
+    let longest = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) as f32;
+    let width = ((longest * size * 0.55) as u32 + 2 * padding).min(image.width());
+    let height = ((lines.len() as f32 * size * 1.3) as u32 + 2 * padding).min(image.height());
+    let (x, y) = (0, image.height() - height);
+
+    for pixel_y in y..y + height {
+        for pixel_x in x..x + width {
+            let pixel = image.get_pixel_mut(pixel_x, pixel_y);
+            for channel in 0..3 {
+                pixel[channel] = (PANEL[channel] as f32 * PANEL_OPACITY
+                    + pixel[channel] as f32 * (1.0 - PANEL_OPACITY)) as u8;
+            }
+        }
+    }
+    for (index, line) in lines.iter().enumerate() {
+        let line_y = y + padding + (index as f32 * size * 1.3) as u32;
+        imageproc::drawing::draw_text_mut(
+            &mut image,
+            TEXT,
+            (x + padding) as i32,
+            line_y as i32,
+            Scale::uniform(size),
+            &font,
+            line,
+        );
+    }
+
+    let mut encoded = std::io::Cursor::new(Vec::new());
+    let image = image::DynamicImage::ImageRgba8(image);
+    // JPEG has no alpha channel
+    let image = match output_format {
+        ImageOutputFormat::Jpeg(_) => image::DynamicImage::ImageRgb8(image.to_rgb8()),
+        _ => image,
+    };
+    image
+        .write_to(&mut encoded, output_format)
+        .map_err(|e| format!("Failed to encode overlay: {}", e))?;
+
+    Ok((
+        encoded.into_inner(),
+        Overlay {
+            x,
+            y,
+            width,
+            height,
+            lines,
+        },
+    ))
+}
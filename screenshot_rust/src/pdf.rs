@@ -0,0 +1,84 @@
+// `--format pdf`: the page printed to PDF, or with `--selector` only the selected element on a page of its size.
+
+use crate::{evaluate, Args, Rect, ScreenshotResult};
+use chromiumoxide::cdp::browser_protocol::page::PrintToPdfParams;
+use chromiumoxide::Page;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// The element printed with `--format pdf --selector`.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ElementPdf {
+    pub selector: String,
+    /// the element's box, which is also the page size.
+    pub rect: Rect,
+    pub size: usize,
+}
+
+/// CSS pixels per inch, the unit Chrome takes paper sizes in.
+const PIXELS_PER_INCH: f64 = 96.0;
+
+/// Print the page, or the `--selector` element alone, to PDF.
+pub async fn capture(page: &Page, args: &Args, result: &mut ScreenshotResult) -> Result<Vec<u8>, String> {
+    let Some(selector) = &args.selector else {
+        return print(page, PrintToPdfParams::builder().print_background(true).build()).await;
+    };
+
+    // Hide everything but the element for print and move it to the top left of a page its own size,
+    // the rest of the document keeps its layout so only the first page is printed
+    let rect: Option<Rect> = evaluate(
+        page,
+        &format!(
+            r#"(() => {{
+                const elements = document.querySelectorAll({});
+                const element = elements[{}];
+                if (!element) return null;
+                const rect = element.getBoundingClientRect();
+                element.setAttribute('data-screenshot-pdf', '');
+                const style = document.createElement('style');
+                style.textContent = `@page {{ size: ${{rect.width}}px ${{rect.height}}px; margin: 0 }}
+                    @media print {{
+                        body * {{ visibility: hidden !important }}
+                        [data-screenshot-pdf], [data-screenshot-pdf] * {{ visibility: visible !important }}
+                        [data-screenshot-pdf] {{ position: fixed !important; left: 0 !important; top: 0 !important; margin: 0 !important; width: ${{rect.width}}px !important; box-sizing: border-box !important }}
+                    }}`;
+                document.head.appendChild(style);
+                return {{ x: rect.left + window.scrollX, y: rect.top + window.scrollY, width: rect.width, height: rect.height }};
+            }})()"#,
+            serde_json::to_string(selector).map_err(|e| e.to_string())?,
+            args.element_index.unwrap_or(0),
+        ),
+    )
+    .await?;
+    let rect = rect
+        .filter(|rect| rect.width > 0.0 && rect.height > 0.0)
+        .ok_or_else(|| format!("Selector '{}' matched no visible element to print", selector))?;
+
+    let pdf = print(
+        page,
+        PrintToPdfParams::builder()
+            .print_background(true)
+            .prefer_css_page_size(true)
+            .paper_width(rect.width / PIXELS_PER_INCH)
+            .paper_height(rect.height / PIXELS_PER_INCH)
+            .margin_top(0.0)
+            .margin_bottom(0.0)
+            .margin_left(0.0)
+            .margin_right(0.0)
+            .page_ranges("1")
+            .build(),
+    )
+    .await?;
+
+    result.element_index = Some(args.element_index.unwrap_or(0));
+    result.element_pdf = Some(ElementPdf {
+        selector: selector.clone(),
+        rect,
+        size: pdf.len(),
+    });
+    Ok(pdf)
+}
+
+async fn print(page: &Page, params: PrintToPdfParams) -> Result<Vec<u8>, String> {
+    page.pdf(params).await.map_err(|e| format!("Failed to print PDF: {}", e))
+}
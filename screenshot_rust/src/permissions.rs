@@ -0,0 +1,101 @@
+use chromiumoxide::cdp::browser_protocol::browser::{
+    GrantPermissionsParams, PermissionDescriptor, PermissionSetting, PermissionType, ResetPermissionsParams,
+    SetPermissionParams,
+};
+use chromiumoxide::Browser;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Permission names accepted on the command line.
+pub const NAMES: [&str; 5] = ["notifications", "geolocation", "camera", "microphone", "clipboard"];
+
+/// A permission applied for the captured origin.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AppliedPermission {
+    pub name: String,
+    /// `granted` or `denied`.
+    pub setting: String,
+}
+
+/// The CDP permission types behind a command line permission name.
+fn permission_types(name: &str) -> Vec<PermissionType> {
+    match name {
+        "notifications" => vec![PermissionType::Notifications],
+        "geolocation" => vec![PermissionType::Geolocation],
+        "camera" => vec![PermissionType::VideoCapture],
+        "microphone" => vec![PermissionType::AudioCapture],
+        "clipboard" => vec![PermissionType::ClipboardReadWrite, PermissionType::ClipboardSanitizedWrite],
+        _ => Vec::new(),
+    }
+}
+
+/// The Permissions API descriptor names behind a command line permission name.
+fn descriptor_names(name: &str) -> Vec<&'static str> {
+    match name {
+        "notifications" => vec!["notifications"],
+        "geolocation" => vec!["geolocation"],
+        "camera" => vec!["camera"],
+        "microphone" => vec!["microphone"],
+        "clipboard" => vec!["clipboard-read", "clipboard-write"],
+        _ => Vec::new(),
+    }
+}
+
+/// Reset the permissions of `origin`, then grant and deny the given ones.
+pub async fn apply(
+    browser: &Browser,
+    origin: &str,
+    grant: &[String],
+    deny: &[String],
+) -> Result<Vec<AppliedPermission>, String> {
+    let mut applied = Vec::new();
+
+    browser
+        .execute(ResetPermissionsParams::default())
+        .await
+        .map_err(|e| format!("Failed to reset permissions: {}", e))?;
+
+    if !grant.is_empty() {
+        let permissions = grant
+            .iter()
+            .flat_map(|name| permission_types(name))
+            .collect::<Vec<PermissionType>>();
+
+        browser
+            .execute(
+                GrantPermissionsParams::builder()
+                    .permissions(permissions)
+                    .origin(origin)
+                    .build()?,
+            )
+            .await
+            .map_err(|e| format!("Failed to grant permissions: {}", e))?;
+
+        applied.extend(grant.iter().map(|name| AppliedPermission {
+            name: name.clone(),
+            setting: "granted".to_string(),
+        }));
+    }
+
+    for name in deny {
+        for descriptor in descriptor_names(name) {
+            browser
+                .execute(
+                    SetPermissionParams::builder()
+                        .permission(PermissionDescriptor::new(descriptor))
+                        .setting(PermissionSetting::Denied)
+                        .origin(origin)
+                        .build()?,
+                )
+                .await
+                .map_err(|e| format!("Failed to deny permission '{}': {}", name, e))?;
+        }
+
+        applied.push(AppliedPermission {
+            name: name.clone(),
+            setting: "denied".to_string(),
+        });
+    }
+
+    Ok(applied)
+}
@@ -0,0 +1,135 @@
+// `--output-placeholder-on-failure`: a stand-in image for pipelines that expect `--output` to exist.
+
+use image::{ImageFormat, Rgb, RgbImage};
+use rusttype::{Font, Scale};
+
+const BACKGROUND: Rgb<u8> = Rgb([0xee, 0xee, 0xee]);
+const TEXT: Rgb<u8> = Rgb([0x99, 0x1b, 0x1b]);
+
+/// Most pixels a placeholder is drawn with, larger ones are scaled down to fit.
+const MAX_PIXELS: u64 = 4096 * 4096;
+
+/// Fonts tried for the error text, the image is written without text when none is installed.
+const FONT_PATHS: [&str; 5] = [
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+];
+
+/// Write a `width` x `height` placeholder showing `error` to `path`, returning whether the text was drawn.
+///
+/// The image is scaled down to at most `max_pixels`, and `MAX_PIXELS` whatever the limit. Without
+/// a font it is crossed out instead of showing the text.
+pub fn write(path: &str, width: u32, height: u32, max_pixels: Option<u64>, error: &str) -> Result<bool, String> {
+    let (width, height) = fit(width, height, max_pixels.unwrap_or(MAX_PIXELS).min(MAX_PIXELS));
+    let mut image = RgbImage::from_pixel(width, height, BACKGROUND);
+    let font = font();
+
+    if let Some(font) = &font {
+        let size = (width as f32 / 60.0).clamp(14.0, 32.0);
+        let margin = (size * 2.0) as i32;
+        // glyphs average about half their height in width
+        let columns = ((width as f32 - 2.0 * margin as f32) / (size * 0.55)).max(10.0) as usize;
+
+        let lines = std::iter::once("Screenshot failed".to_string()).chain(wrap(error, columns));
+        for (index, line) in lines.enumerate() {
+            let y = margin + (index as f32 * size * 1.4) as i32;
+            if y as f32 + size > height as f32 {
+                break;
+            }
+            imageproc::drawing::draw_text_mut(&mut image, TEXT, margin, y, Scale::uniform(size), font, &line);
+        }
+    } else {
+        let (right, bottom) = (width as f32 - 1.0, height as f32 - 1.0);
+        imageproc::drawing::draw_line_segment_mut(&mut image, (0.0, 0.0), (right, bottom), TEXT);
+        imageproc::drawing::draw_line_segment_mut(&mut image, (right, 0.0), (0.0, bottom), TEXT);
+    }
+
+    // formats the image crate cannot encode here get a PNG under the requested name
+    let format = match ImageFormat::from_path(path) {
+        Ok(ImageFormat::Jpeg) => ImageFormat::Jpeg,
+        _ => ImageFormat::Png,
+    };
+
+    image
+        .save_with_format(path, format)
+        .map_err(|e| format!("Failed to save placeholder: {}", e))?;
+
+    Ok(font.is_some())
+}
+
+/// The first of `FONT_PATHS` installed.
+pub fn font() -> Option<Font<'static>> {
+    FONT_PATHS
+        .iter()
+        .filter_map(|path| std::fs::read(path).ok())
+        .find_map(Font::try_from_vec)
+}
+
+/// `width` x `height` scaled down to at most `max_pixels`, keeping the aspect ratio where it can.
+fn fit(width: u32, height: u32, max_pixels: u64) -> (u32, u32) {
+    let (width, height) = (width.max(1) as u64, height.max(1) as u64);
+    let max_pixels = max_pixels.max(1);
+    if width * height <= max_pixels {
+        return (width as u32, height as u32);
+    }
+
+    let scale = (max_pixels as f64 / (width * height) as f64).sqrt();
+    let fitted_width = ((width as f64 * scale) as u64).max(1);
+    // a very thin image cannot keep its ratio, its long side is cut to the budget instead
+    let fitted_height = ((height as f64 * scale) as u64).clamp(1, max_pixels / fitted_width);
+
+    (fitted_width as u32, fitted_height as u32)
+}
+
+/// Break `text` into lines of at most `columns` characters at word boundaries.
+fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > columns {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_large_placeholders_into_the_pixel_budget() {
+        assert_eq!(fit(1920, 1080, MAX_PIXELS), (1920, 1080));
+        assert_eq!(fit(0, 0, MAX_PIXELS), (1, 1));
+        assert_eq!(fit(40_000, 40_000, 1_000_000), (1000, 1000));
+
+        let (width, height) = fit(1, u32::MAX, MAX_PIXELS);
+        assert_eq!(width, 1);
+        assert!(height as u64 <= MAX_PIXELS);
+    }
+
+    #[test]
+    fn writes_a_placeholder_with_or_without_a_font() {
+        let path = std::env::temp_dir().join(format!("placeholder-{}.png", uuid::Uuid::new_v4()));
+        let path = path.to_string_lossy();
+
+        let drew_text = write(&path, 100_000, 100_000, Some(10_000), "net::ERR_NAME_NOT_RESOLVED").unwrap();
+        let image = image::open(path.as_ref()).unwrap();
+        let _ = std::fs::remove_file(path.as_ref());
+
+        assert_eq!((image.width(), image.height()), (100, 100));
+        assert_eq!(drew_text, font().is_some());
+    }
+}
@@ -0,0 +1,434 @@
+use crate::metadata;
+use crate::overlay;
+use crate::{suffixed_path, Args, ScreenshotResult};
+use image::imageops::FilterType;
+use image::ImageEncoder;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// The encoding of a re-encoded PNG.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct PngEncoding {
+    /// `grayscale`, `truecolor`, `truecolor-alpha` or `palette`.
+    pub color_type: String,
+    pub bit_depth: u8,
+}
+
+/// The encoding of a re-encoded JPEG.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct JpegEncoding {
+    pub quality: u8,
+    pub progressive: bool,
+    pub subsampling: String,
+}
+
+/// The encoding of an AVIF output.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AvifEncoding {
+    pub quality: u8,
+    /// 0 (fastest) to 10 (smallest file).
+    pub effort: u8,
+    /// the encoder speed the effort maps to, 10 being the fastest.
+    pub speed: u8,
+    /// bits per channel, 8 only for effort 0.
+    pub bit_depth: u8,
+    pub size: usize,
+}
+
+/// The encoding of a WebP output encoded here rather than by Chrome.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WebpEncoding {
+    pub lossless: bool,
+    pub size: usize,
+}
+
+/// Effort used when `--encoding-effort` is not given, the encoder's own default speed of 4.
+const DEFAULT_AVIF_EFFORT: u8 = 7;
+
+/// A square icon generated from the capture.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Icon {
+    pub file_path: String,
+    /// width and height in pixels.
+    pub size: u32,
+    pub center_cropped: bool,
+}
+
+/// The untouched capture saved next to the processed one with `--keep-original`.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct KeptOriginal {
+    pub file_path: String,
+    pub size: usize,
+    /// size of the processed output, to compare against.
+    pub processed_size: usize,
+}
+
+/// The decode budget of `--max-decode-pixels`, reported when a capture exceeded it.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct DecodeGuard {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: u64,
+    pub limit: u64,
+}
+
+/// Apply the requested post-processing to the captured bytes.
+///
+/// Without any post-processing flag the bytes are returned exactly as Chrome produced them.
+pub fn apply(args: &Args, screenshot_data: Vec<u8>, result: &mut ScreenshotResult) -> Result<Vec<u8>, String> {
+    let decodes = args.overlay_metrics
+        || args.icon_size.is_some()
+        || (result.format == "png" && (args.png_color_type.is_some() || args.output_bit_depth.is_some()))
+        || args.reencodes_jpeg()
+        || result.format == "avif"
+        || args.webp_lossless;
+    if decodes {
+        check_decode_budget(args, &screenshot_data, result)?;
+    }
+
+    let original = args.keep_original.then(|| screenshot_data.clone());
+    let mut screenshot_data = screenshot_data;
+
+    // First, so the icon and every re-encoding include the panel
+    if let Some(metrics) = &result.page_metrics {
+        let (drawn, overlay) = overlay::draw(&screenshot_data, metrics, args.quality.clamp(1, 100))?;
+        screenshot_data = drawn;
+        result.overlay = Some(overlay);
+    }
+
+    if let Some(size) = args.icon_size {
+        result.icon = Some(write_icon(&screenshot_data, size, &args.output)?);
+    }
+
+    if result.format == "png" && (args.png_color_type.is_some() || args.output_bit_depth.is_some()) {
+        let color_type = args.png_color_type.as_deref().unwrap_or("truecolor-alpha");
+        // palette images only come in 8-bit
+        let bit_depth = match color_type {
+            "palette" => 8,
+            _ => args.output_bit_depth.unwrap_or(8),
+        };
+
+        screenshot_data = encode_png(&screenshot_data, color_type, bit_depth)?;
+        result.png_encoding = Some(PngEncoding {
+            color_type: color_type.to_string(),
+            bit_depth,
+        });
+    }
+
+    if args.reencodes_jpeg() {
+        let encoding = JpegEncoding {
+            quality: args.quality.clamp(1, 100),
+            progressive: args.jpeg_progressive,
+            subsampling: args.jpeg_subsampling.clone().unwrap_or_else(|| "4:2:0".to_string()),
+        };
+
+        screenshot_data = encode_jpeg(&screenshot_data, &encoding)?;
+        result.jpeg_encoding = Some(encoding);
+    }
+
+    if result.format == "avif" {
+        let (encoded, encoding) = encode_avif(&screenshot_data, avif_effort(args), args.quality)?;
+
+        screenshot_data = encoded;
+        result.avif_encoding = Some(encoding);
+    }
+
+    if args.webp_lossless && result.format == "webp" {
+        screenshot_data = encode_webp_lossless(&screenshot_data)?;
+        result.webp_encoding = Some(WebpEncoding {
+            lossless: true,
+            size: screenshot_data.len(),
+        });
+    }
+
+    // Last so nothing re-encodes the metadata away
+    if args.embed_metadata {
+        let (embedded, fields) = metadata::embed(&screenshot_data, result.provenance())?;
+
+        screenshot_data = embedded;
+        result.embedded_metadata = Some(fields);
+    }
+
+    // Only worth a second file when something above changed the bytes
+    if let Some(original) = original.filter(|original| *original != screenshot_data) {
+        result.original = Some(write_original(&original, screenshot_data.len(), &args.output)?);
+    }
+
+    Ok(screenshot_data)
+}
+
+/// Encode a side capture (journey step, time-lapse frame, ...) like `apply` encodes the output,
+/// so the bytes written under the output's extension are in its format.
+pub fn encode_side_capture(args: &Args, screenshot_data: Vec<u8>) -> Result<Vec<u8>, String> {
+    // the browser chrome window grab stays PNG, as the main output does
+    if args.with_browser_chrome {
+        return Ok(screenshot_data);
+    }
+
+    match args.format.as_str() {
+        "avif" => encode_avif(&screenshot_data, avif_effort(args), args.quality).map(|(encoded, _)| encoded),
+        "webp" if args.webp_lossless => encode_webp_lossless(&screenshot_data),
+        _ => Ok(screenshot_data),
+    }
+}
+
+/// The AVIF effort asked for, or the default.
+fn avif_effort(args: &Args) -> u8 {
+    args.encoding_effort.unwrap_or(DEFAULT_AVIF_EFFORT)
+}
+
+/// The encoder speed and bit depth of an effort. The encoder takes a speed from 10 (fastest)
+/// down to 1, so effort 0 shares speed 10 with effort 1 but skips the 10-bit conversion.
+fn avif_settings(effort: u8) -> (u8, ravif::BitDepth) {
+    match effort.min(10) {
+        0 => (10, ravif::BitDepth::Eight),
+        effort => (11 - effort, ravif::BitDepth::Ten),
+    }
+}
+
+/// Fail before decoding when the image header announces more pixels than `--max-decode-pixels`.
+pub fn check_decode_budget(args: &Args, data: &[u8], result: &mut ScreenshotResult) -> Result<(), String> {
+    if args.max_decode_pixels.is_none() {
+        return Ok(());
+    }
+
+    let (width, height) = image::io::Reader::new(std::io::Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|e| format!("Failed to read image header: {}", e))?
+        .into_dimensions()
+        .map_err(|e| format!("Failed to read image header: {}", e))?;
+
+    check_pixel_budget(args, width, height, result)
+}
+
+/// Fail before allocating a `width` x `height` image of more pixels than `--max-decode-pixels`.
+pub fn check_pixel_budget(args: &Args, width: u32, height: u32, result: &mut ScreenshotResult) -> Result<(), String> {
+    let Some(limit) = args.max_decode_pixels else {
+        return Ok(());
+    };
+    let pixels = width as u64 * height as u64;
+
+    if pixels > limit {
+        result.decode_guard = Some(DecodeGuard {
+            width,
+            height,
+            pixels,
+            limit,
+        });
+        return Err(format!(
+            "Image of {}x{} ({} pixels) exceeds --max-decode-pixels {}, not decoding it",
+            width, height, pixels, limit
+        ));
+    }
+
+    Ok(())
+}
+
+/// Center-crop the capture to a square, resize it to `size` and save it as PNG next to `output`.
+fn write_icon(data: &[u8], size: u32, output: &str) -> Result<Icon, String> {
+    let image = image::load_from_memory(data).map_err(|e| format!("Failed to decode screenshot: {}", e))?;
+    let side = image.width().min(image.height());
+    let icon = image
+        .crop_imm((image.width() - side) / 2, (image.height() - side) / 2, side, side)
+        .resize_exact(size, size, FilterType::Lanczos3);
+
+    let file_path = suffixed_path(output, &format!("-icon-{}", size));
+    let file_path = std::path::Path::new(&file_path)
+        .with_extension("png")
+        .to_string_lossy()
+        .into_owned();
+
+    icon.save_with_format(&file_path, image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to save icon: {}", e))?;
+
+    Ok(Icon {
+        file_path,
+        size,
+        center_cropped: true,
+    })
+}
+
+/// Save the capture as Chrome returned it to a `.orig` sibling of `output`, with its own extension.
+fn write_original(data: &[u8], processed_size: usize, output: &str) -> Result<KeptOriginal, String> {
+    let extension = if data.starts_with(&[0xff, 0xd8]) {
+        "jpg"
+    } else if data.starts_with(b"RIFF") {
+        "webp"
+    } else {
+        "png"
+    };
+    let file_path = std::path::Path::new(&suffixed_path(output, ".orig"))
+        .with_extension(extension)
+        .to_string_lossy()
+        .into_owned();
+
+    std::fs::write(&file_path, data).map_err(|e| format!("Failed to save original: {}", e))?;
+
+    Ok(KeptOriginal {
+        file_path,
+        size: data.len(),
+        processed_size,
+    })
+}
+
+/// Decode the image and write it back as PNG with the given color type and bit depth.
+fn encode_png(data: &[u8], color_type: &str, bit_depth: u8) -> Result<Vec<u8>, String> {
+    let image = image::load_from_memory(data).map_err(|e| format!("Failed to decode screenshot: {}", e))?;
+    let (width, height) = (image.width(), image.height());
+    let sixteen = bit_depth == 16;
+
+    let (color, pixels, palette) = match color_type {
+        "grayscale" if sixteen => (png::ColorType::Grayscale, to_be_bytes(image.to_luma16().into_raw()), None),
+        "grayscale" => (png::ColorType::Grayscale, image.to_luma8().into_raw(), None),
+        "truecolor" if sixteen => (png::ColorType::Rgb, to_be_bytes(image.to_rgb16().into_raw()), None),
+        "truecolor" => (png::ColorType::Rgb, image.to_rgb8().into_raw(), None),
+        "palette" => {
+            let rgba = image.to_rgba8().into_raw();
+            let quantizer = color_quant::NeuQuant::new(10, 256, &rgba);
+            let indices = rgba
+                .chunks_exact(4)
+                .map(|pixel| quantizer.index_of(pixel) as u8)
+                .collect::<Vec<u8>>();
+
+            (png::ColorType::Indexed, indices, Some(quantizer.color_map_rgba()))
+        }
+        _ if sixteen => (png::ColorType::Rgba, to_be_bytes(image.to_rgba16().into_raw()), None),
+        _ => (png::ColorType::Rgba, image.to_rgba8().into_raw(), None),
+    };
+
+    let mut encoded = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut encoded, width, height);
+        encoder.set_color(color);
+
+        if let Some(palette) = palette {
+            // palette images are always 8-bit, split the RGBA map into PLTE and tRNS
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_palette(palette.chunks_exact(4).flat_map(|c| [c[0], c[1], c[2]]).collect::<Vec<u8>>());
+            encoder.set_trns(palette.chunks_exact(4).map(|c| c[3]).collect::<Vec<u8>>());
+        } else {
+            encoder.set_depth(if sixteen { png::BitDepth::Sixteen } else { png::BitDepth::Eight });
+        }
+
+        let mut writer = encoder
+            .write_header()
+            .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+        writer
+            .write_image_data(&pixels)
+            .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+        writer.finish().map_err(|e| format!("Failed to encode PNG: {}", e))?;
+    }
+
+    Ok(encoded)
+}
+
+/// Decode the image and write it back as JPEG with the given encoder settings.
+fn encode_jpeg(data: &[u8], encoding: &JpegEncoding) -> Result<Vec<u8>, String> {
+    let image = image::load_from_memory(data)
+        .map_err(|e| format!("Failed to decode screenshot: {}", e))?
+        .to_rgb8();
+    let (width, height) = match (u16::try_from(image.width()), u16::try_from(image.height())) {
+        (Ok(width), Ok(height)) => (width, height),
+        _ => return Err("Image is too large for JPEG (65535 pixels at most per side)".to_string()),
+    };
+
+    let mut encoded = Vec::new();
+    let mut encoder = jpeg_encoder::Encoder::new(&mut encoded, encoding.quality);
+    encoder.set_progressive(encoding.progressive);
+    encoder.set_sampling_factor(match encoding.subsampling.as_str() {
+        "4:4:4" => jpeg_encoder::SamplingFactor::R_4_4_4,
+        "4:2:2" => jpeg_encoder::SamplingFactor::R_4_2_2,
+        _ => jpeg_encoder::SamplingFactor::R_4_2_0,
+    });
+    encoder
+        .encode(image.as_raw(), width, height, jpeg_encoder::ColorType::Rgb)
+        .map_err(|e| format!("Failed to encode JPEG: {}", e))?;
+
+    Ok(encoded)
+}
+
+/// Decode the lossless capture and encode it as AVIF at the given effort.
+fn encode_avif(data: &[u8], effort: u8, quality: u8) -> Result<(Vec<u8>, AvifEncoding), String> {
+    let (speed, bit_depth) = avif_settings(effort);
+    let quality = quality.clamp(1, 100);
+
+    let image = image::load_from_memory(data)
+        .map_err(|e| format!("Failed to decode screenshot: {}", e))?
+        .to_rgba8();
+    let pixels = image
+        .as_raw()
+        .chunks_exact(4)
+        .map(|p| ravif::RGBA8::new(p[0], p[1], p[2], p[3]))
+        .collect::<Vec<ravif::RGBA8>>();
+
+    let encoded = ravif::Encoder::new()
+        .with_quality(quality as f32)
+        .with_speed(speed)
+        .with_bit_depth(bit_depth)
+        .encode_rgba(ravif::Img::new(pixels.as_slice(), image.width() as usize, image.height() as usize))
+        .map_err(|e| format!("Failed to encode AVIF: {}", e))?
+        .avif_file;
+
+    let encoding = AvifEncoding {
+        quality,
+        effort,
+        speed,
+        bit_depth: if matches!(bit_depth, ravif::BitDepth::Eight) { 8 } else { 10 },
+        size: encoded.len(),
+    };
+
+    Ok((encoded, encoding))
+}
+
+/// Decode the lossless capture and encode it as lossless WebP.
+fn encode_webp_lossless(data: &[u8]) -> Result<Vec<u8>, String> {
+    let image = image::load_from_memory(data)
+        .map_err(|e| format!("Failed to decode screenshot: {}", e))?
+        .to_rgba8();
+
+    let mut encoded = Vec::new();
+    image::codecs::webp::WebPEncoder::new_lossless(&mut encoded)
+        .write_image(image.as_raw(), image.width(), image.height(), image::ColorType::Rgba8)
+        .map_err(|e| format!("Failed to encode WebP: {}", e))?;
+
+    Ok(encoded)
+}
+
+/// PNG stores 16-bit samples big endian.
+fn to_be_bytes(samples: Vec<u16>) -> Vec<u8> {
+    samples.into_iter().flat_map(u16::to_be_bytes).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_effort_has_its_own_settings() {
+        let speeds = (0..=10).map(|effort| avif_settings(effort).0).collect::<Vec<u8>>();
+        assert_eq!(speeds, [10, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1]);
+        assert!(matches!(avif_settings(0).1, ravif::BitDepth::Eight));
+        assert!(matches!(avif_settings(1).1, ravif::BitDepth::Ten));
+        assert_eq!(avif_settings(DEFAULT_AVIF_EFFORT).0, 4);
+    }
+
+    #[test]
+    fn reports_the_avif_encoding_it_used() {
+        let image = image::RgbaImage::from_fn(64, 64, |x, y| image::Rgba([(x * 4) as u8, (y * 4) as u8, (x ^ y) as u8, 255]));
+        let mut png = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageFormat::Png).unwrap();
+
+        let (fastest, fastest_encoding) = encode_avif(png.get_ref(), 0, 50).unwrap();
+        assert_eq!(&fastest[4..12], b"ftypavif");
+        assert_eq!(fastest_encoding.effort, 0);
+        assert_eq!(fastest_encoding.speed, 10);
+        assert_eq!(fastest_encoding.bit_depth, 8);
+        assert_eq!(fastest_encoding.size, fastest.len());
+
+        let (smallest, smallest_encoding) = encode_avif(png.get_ref(), 10, 50).unwrap();
+        assert_eq!(smallest_encoding.effort, 10);
+        assert_eq!(smallest_encoding.speed, 1);
+        assert_eq!(smallest_encoding.bit_depth, 10);
+        assert_eq!(smallest_encoding.size, smallest.len());
+    }
+}
@@ -0,0 +1,104 @@
+// A small preview of the capture printed to the terminal, for instant feedback without opening the file.
+
+use image::imageops::FilterType;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::{IsTerminal, Write};
+
+/// The preview printed with `--ascii`.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AsciiPreview {
+    /// `truecolor`, `256color` or `plain`, picked from the terminal's capabilities.
+    pub mode: String,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+/// Widest preview printed, however wide the terminal.
+const MAX_COLUMNS: u32 = 100;
+
+/// Darkest to lightest, for terminals without color.
+const RAMP: &[u8] = b"@%#*+=-:. ";
+
+/// Print a downsampled preview of the image to stderr, leaving stdout to the JSON result.
+pub fn print(data: &[u8]) -> Result<AsciiPreview, String> {
+    let image = image::load_from_memory(data).map_err(|e| format!("Failed to decode screenshot: {}", e))?;
+    let mode = color_mode();
+
+    let columns = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse::<u32>().ok())
+        .unwrap_or(80)
+        .clamp(1, MAX_COLUMNS)
+        .min(image.width().max(1));
+    // Terminal cells are about twice as tall as wide; colored half blocks pack two pixels per cell
+    let pixel_rows = match mode {
+        "plain" => (image.height() as u64 * columns as u64 / image.width().max(1) as u64 / 2).max(1) as u32,
+        _ => (image.height() as u64 * columns as u64 / image.width().max(1) as u64).max(2) as u32,
+    };
+    let small = image.resize_exact(columns, pixel_rows, FilterType::Triangle).to_rgb8();
+
+    let mut out = String::new();
+    if mode == "plain" {
+        for y in 0..small.height() {
+            for x in 0..small.width() {
+                let [r, g, b] = small.get_pixel(x, y).0;
+                let luma = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+                out.push(RAMP[luma as usize * (RAMP.len() - 1) / 255] as char);
+            }
+            out.push('\n');
+        }
+    } else {
+        for y in (0..small.height() - 1).step_by(2) {
+            for x in 0..small.width() {
+                let top = small.get_pixel(x, y).0;
+                let bottom = small.get_pixel(x, y + 1).0;
+                // the upper half block takes the top pixel as foreground, the bottom as background
+                if mode == "truecolor" {
+                    out.push_str(&format!(
+                        "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+                        top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+                    ));
+                } else {
+                    out.push_str(&format!("\x1b[38;5;{}m\x1b[48;5;{}m\u{2580}", ansi256(top), ansi256(bottom)));
+                }
+            }
+            out.push_str("\x1b[0m\n");
+        }
+    }
+
+    let mut stderr = std::io::stderr();
+    stderr
+        .write_all(out.as_bytes())
+        .and_then(|_| stderr.flush())
+        .map_err(|e| format!("Failed to print preview: {}", e))?;
+
+    Ok(AsciiPreview {
+        mode: mode.to_string(),
+        columns,
+        rows: if mode == "plain" { pixel_rows } else { pixel_rows / 2 },
+    })
+}
+
+/// The richest color the terminal on stderr supports, plain text when it is not a terminal.
+fn color_mode() -> &'static str {
+    if !std::io::stderr().is_terminal() || std::env::var_os("NO_COLOR").is_some() {
+        return "plain";
+    }
+
+    let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+    let term = std::env::var("TERM").unwrap_or_default();
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        "truecolor"
+    } else if term.contains("256color") {
+        "256color"
+    } else {
+        "plain"
+    }
+}
+
+/// The nearest color of the 6x6x6 cube of the 256 color palette.
+fn ansi256([r, g, b]: [u8; 3]) -> u8 {
+    let level = |c: u8| ((c as u16 * 5 + 127) / 255) as u8;
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
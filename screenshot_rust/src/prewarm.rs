@@ -0,0 +1,62 @@
+// `--prewarm`: resolve and start Chrome once so the first real capture of a fresh container is not cold.
+
+use crate::{chrome_version, launch_browser, Args};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// Outcome of prewarming.
+#[derive(Serialize, Deserialize)]
+pub struct Prewarm {
+    pub ready: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chrome_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chrome_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub launch_ms: Option<u64>,
+    pub total_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Find the Chrome binary, launch it and render a blank page to fill its disk caches.
+pub async fn run(args: &Args) -> Prewarm {
+    let started = Instant::now();
+    let mut prewarm = Prewarm {
+        ready: false,
+        chrome_path: None,
+        chrome_version: None,
+        launch_ms: None,
+        total_ms: 0,
+        error: None,
+    };
+
+    if let Err(e) = warm(args, &mut prewarm, started).await {
+        prewarm.error = Some(e);
+    }
+
+    prewarm.total_ms = started.elapsed().as_millis() as u64;
+    prewarm
+}
+
+async fn warm(args: &Args, prewarm: &mut Prewarm, started: Instant) -> Result<(), String> {
+    // the same lookup the browser launch does, CHROME env var first
+    let path = chromiumoxide::detection::default_executable(Default::default())
+        .map_err(|e| format!("Failed to find Chrome: {}", e))?;
+    prewarm.chrome_path = Some(path.to_string_lossy().into_owned());
+
+    let (mut browser, _cert_home) = launch_browser(args, &[]).await?;
+    prewarm.launch_ms = Some(started.elapsed().as_millis() as u64);
+    prewarm.chrome_version = Some(chrome_version(&browser).await?);
+
+    let page = browser
+        .new_page("about:blank")
+        .await
+        .map_err(|e| format!("Failed to create new page: {}", e))?;
+    let _ = page.close().await;
+    let _ = browser.close().await;
+    let _ = browser.wait().await;
+
+    prewarm.ready = true;
+    Ok(())
+}
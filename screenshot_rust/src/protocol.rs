@@ -0,0 +1,96 @@
+// Binary framing used to hand a capture to a parent process over a file descriptor.
+// examples/screenshot-protocol.mjs reads it from Node.
+//
+// A frame is laid out as:
+//
+//   4 bytes  magic `SSRP`
+//   1 byte   protocol version
+//   8 bytes  image length (unsigned, big endian)
+//   n bytes  raw image data
+//   4 bytes  JSON trailer length (unsigned, big endian)
+//   m bytes  UTF-8 JSON of the `ScreenshotResult`
+//
+// A failed capture still writes a frame with an empty image so the parent always
+// has a trailer to read. The process exit code mirrors `success` (0 or 1).
+
+use std::io::{self, Write};
+
+/// Magic bytes opening every frame.
+pub const MAGIC: &[u8; 4] = b"SSRP";
+
+/// Current version of the framing.
+pub const VERSION: u8 = 1;
+
+/// Write a single frame holding the image bytes and JSON trailer to `fd`.
+pub fn write_frame(fd: i32, image: &[u8], trailer: &[u8]) -> io::Result<()> {
+    encode_frame(&mut open_fd(fd)?, image, trailer)
+}
+
+fn encode_frame(out: &mut impl Write, image: &[u8], trailer: &[u8]) -> io::Result<()> {
+    let trailer_len = u32::try_from(trailer.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "JSON trailer too large"))?;
+
+    out.write_all(MAGIC)?;
+    out.write_all(&[VERSION])?;
+    out.write_all(&(image.len() as u64).to_be_bytes())?;
+    out.write_all(image)?;
+    out.write_all(&trailer_len.to_be_bytes())?;
+    out.write_all(trailer)?;
+    out.flush()
+}
+
+#[cfg(unix)]
+fn open_fd(fd: i32) -> io::Result<std::fs::File> {
+    use std::os::unix::io::FromRawFd;
+
+    if fd < 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid file descriptor {}", fd),
+        ));
+    }
+
+    // the descriptor is handed to us by the parent and closed once the frame is written
+    // so the reader sees EOF.
+    Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn open_fd(_fd: i32) -> io::Result<std::fs::File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--protocol-fd is only supported on unix platforms",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_the_image_and_trailer_with_big_endian_lengths() {
+        let mut frame = Vec::new();
+        encode_frame(&mut frame, b"\x89PNG", br#"{"success":true}"#).unwrap();
+
+        let mut expected = b"SSRP\x01".to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 4]);
+        expected.extend_from_slice(b"\x89PNG");
+        expected.extend_from_slice(&[0, 0, 0, 16]);
+        expected.extend_from_slice(br#"{"success":true}"#);
+        assert_eq!(frame, expected);
+    }
+
+    #[test]
+    fn a_failed_capture_still_gets_a_trailer() {
+        let mut frame = Vec::new();
+        encode_frame(&mut frame, b"", b"{}").unwrap();
+        assert_eq!(frame, b"SSRP\x01\0\0\0\0\0\0\0\0\0\0\0\x02{}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_a_negative_descriptor() {
+        let error = write_frame(-1, b"", b"{}").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
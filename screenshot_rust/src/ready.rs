@@ -0,0 +1,144 @@
+// `--ready-preset`: readiness gates bundled so a capture waits until the page is actually done.
+//
+// `fast` waits for `document.readyState` to reach `complete`, the load event, and skips `--delay`.
+// `thorough` also skips `--delay` and instead waits, in order, for:
+//   network-idle  no request in flight for 500ms
+//   fonts         `document.fonts.ready` resolved
+//   images        every `<img>` finished loading or failed
+//   dom-stable    no DOM mutation for 500ms
+// Each condition gets up to 30s; one that never holds is reported unsatisfied and the capture goes ahead.
+
+use crate::evaluate;
+use chromiumoxide::Page;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How long each condition may take.
+const CONDITION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long the network or the DOM has to stay quiet.
+const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The conditions a preset waited on.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Readiness {
+    pub preset: String,
+    pub conditions: Vec<ReadyCondition>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReadyCondition {
+    pub name: String,
+    pub satisfied: bool,
+    pub waited_ms: u64,
+}
+
+/// The `fast` preset: `document.readyState` reaching `complete`, which it does with the load event.
+pub async fn fast(page: &Page) -> Result<Readiness, String> {
+    let started = Instant::now();
+    let loaded = poll(|| evaluate::<bool>(page, "document.readyState === 'complete'")).await?;
+
+    Ok(Readiness {
+        preset: "fast".to_string(),
+        conditions: vec![condition("load", loaded, started)],
+    })
+}
+
+/// The `thorough` preset, with `in_flight` tracked since before navigation.
+pub async fn thorough(page: &Page, in_flight: &Arc<Mutex<HashSet<String>>>) -> Result<Readiness, String> {
+    let mut conditions = vec![network_idle(in_flight).await];
+
+    let started = Instant::now();
+    let fonts = tokio::time::timeout(CONDITION_TIMEOUT, evaluate::<bool>(page, "document.fonts.ready.then(() => true)")).await;
+    conditions.push(condition("fonts", matches!(fonts, Ok(Ok(true))), started));
+
+    let started = Instant::now();
+    let images = poll(|| evaluate::<bool>(page, "Array.from(document.images).every(image => image.complete)")).await?;
+    conditions.push(condition("images", images, started));
+
+    let started = Instant::now();
+    let stable: bool = evaluate(
+        page,
+        &format!(
+            r#"new Promise(resolve => {{
+                let timer = setTimeout(() => done(true), {quiet});
+                const observer = new MutationObserver(() => {{
+                    clearTimeout(timer);
+                    timer = setTimeout(() => done(true), {quiet});
+                }});
+                const deadline = setTimeout(() => done(false), {timeout});
+                function done(stable) {{
+                    observer.disconnect();
+                    clearTimeout(timer);
+                    clearTimeout(deadline);
+                    resolve(stable);
+                }}
+                observer.observe(document, {{ subtree: true, childList: true, attributes: true, characterData: true }});
+            }})"#,
+            quiet = QUIET_PERIOD.as_millis(),
+            timeout = CONDITION_TIMEOUT.as_millis()
+        ),
+    )
+    .await?;
+    conditions.push(condition("dom-stable", stable, started));
+
+    Ok(Readiness {
+        preset: "thorough".to_string(),
+        conditions,
+    })
+}
+
+/// Wait until no request has been in flight for `QUIET_PERIOD`.
+async fn network_idle(in_flight: &Arc<Mutex<HashSet<String>>>) -> ReadyCondition {
+    let started = Instant::now();
+    let mut idle_since = None;
+
+    while started.elapsed() < CONDITION_TIMEOUT {
+        let idle = in_flight.lock().map(|in_flight| in_flight.is_empty()).unwrap_or(true);
+
+        match (idle, idle_since) {
+            (true, Some(since)) if Instant::now() - since >= QUIET_PERIOD => {
+                return condition("network-idle", true, started);
+            }
+            (true, None) => idle_since = Some(Instant::now()),
+            (false, _) => idle_since = None,
+            _ => {}
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    condition("network-idle", false, started)
+}
+
+/// Poll `check` until it holds or `CONDITION_TIMEOUT` passes, returning whether it held.
+async fn poll<F, Fut>(check: F) -> Result<bool, String>
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<bool, String>>,
+{
+    let started = Instant::now();
+
+    while started.elapsed() < CONDITION_TIMEOUT {
+        if check().await? {
+            return Ok(true);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    Ok(false)
+}
+
+fn condition(name: &str, satisfied: bool, started: Instant) -> ReadyCondition {
+    ReadyCondition {
+        name: name.to_string(),
+        satisfied,
+        waited_ms: started.elapsed().as_millis() as u64,
+    }
+}
@@ -0,0 +1,230 @@
+// `--script`: an ordered list of steps from a JSON file run against one page, for multi-step captures
+// without a flag for every interaction.
+//
+// [
+//   { "action": "click", "selector": "#accept-cookies" },
+//   { "action": "fill", "selector": "input[name=q]", "value": "shoes" },
+//   { "action": "wait", "selector": ".results" },
+//   { "action": "screenshot", "label": "results" }
+// ]
+
+use crate::{capture_current, evaluate, postprocess, suffixed_path, wait_for_visible, Args, ScreenshotResult};
+use chromiumoxide::Page;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// One step of a script.
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase", deny_unknown_fields)]
+enum Step {
+    Goto {
+        url: String,
+    },
+    /// Wait for `selector` to become visible, or sleep `ms` without one.
+    Wait {
+        selector: Option<String>,
+        ms: Option<u64>,
+        timeout_ms: Option<u64>,
+    },
+    Click {
+        selector: String,
+    },
+    /// Replace the value of an input, firing `input` and `change` like typing would.
+    Fill {
+        selector: String,
+        value: String,
+    },
+    Eval {
+        expression: String,
+    },
+    Screenshot {
+        label: Option<String>,
+    },
+}
+
+impl Step {
+    fn action(&self) -> &'static str {
+        match self {
+            Step::Goto { .. } => "goto",
+            Step::Wait { .. } => "wait",
+            Step::Click { .. } => "click",
+            Step::Fill { .. } => "fill",
+            Step::Eval { .. } => "eval",
+            Step::Screenshot { .. } => "screenshot",
+        }
+    }
+}
+
+/// The outcome of one step, the failed step is the last one run.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct StepOutcome {
+    /// 1-based position in the script.
+    pub step: usize,
+    pub action: String,
+    pub ok: bool,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<usize>,
+    /// what an `eval` step returned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<serde_json::Value>,
+}
+
+/// Wait steps without a `timeout_ms` give up after this long.
+const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Read and validate the steps of the script at `path`.
+fn load(path: &str) -> Result<Vec<Step>, String> {
+    let json = std::fs::read_to_string(path).map_err(|e| format!("Failed to read script '{}': {}", path, e))?;
+    serde_json::from_str(&json).map_err(|e| format!("Invalid script '{}': {}", path, e))
+}
+
+/// Run every step in order, stopping at the first that fails.
+///
+/// The last `screenshot` step's capture is returned, or a capture of the final state when the
+/// script takes none.
+pub async fn run(page: &Page, args: &Args, path: &str, result: &mut ScreenshotResult) -> Result<Vec<u8>, String> {
+    let steps = load(path)?;
+    let mut outcomes = Vec::with_capacity(steps.len());
+    let mut screenshot_data = None;
+
+    for (index, step) in steps.iter().enumerate() {
+        let started = Instant::now();
+        let mut outcome = StepOutcome {
+            step: index + 1,
+            action: step.action().to_string(),
+            ok: false,
+            duration_ms: 0,
+            error: None,
+            label: None,
+            file_path: None,
+            size: None,
+            value: None,
+        };
+
+        let ran = run_step(page, args, step, &mut outcome, result).await;
+        outcome.duration_ms = started.elapsed().as_millis() as u64;
+
+        match ran {
+            Ok(data) => {
+                outcome.ok = true;
+                outcomes.push(outcome);
+                if data.is_some() {
+                    screenshot_data = data;
+                }
+            }
+            Err(e) => {
+                outcome.error = Some(e.clone());
+                outcomes.push(outcome);
+                result.script_steps = Some(outcomes);
+                return Err(format!("Script step {} ({}) failed: {}", index + 1, step.action(), e));
+            }
+        }
+    }
+
+    result.script_steps = Some(outcomes);
+    match screenshot_data {
+        Some(data) => Ok(data),
+        None => capture_current(page, args, result).await,
+    }
+}
+
+/// Run one step, returning the capture of a `screenshot` step.
+async fn run_step(
+    page: &Page,
+    args: &Args,
+    step: &Step,
+    outcome: &mut StepOutcome,
+    result: &mut ScreenshotResult,
+) -> Result<Option<Vec<u8>>, String> {
+    match step {
+        Step::Goto { url } => {
+            page.goto(url.as_str())
+                .await
+                .map_err(|e| format!("Failed to navigate to '{}': {}", url, e))?;
+            page.wait_for_navigation()
+                .await
+                .map_err(|e| format!("Failed to wait for navigation to '{}': {}", url, e))?;
+        }
+        Step::Wait { selector, ms, timeout_ms } => {
+            if let Some(selector) = selector {
+                let timeout = timeout_ms.map(Duration::from_millis).unwrap_or(DEFAULT_WAIT_TIMEOUT);
+                wait_for_visible(page, selector, timeout).await?;
+            }
+            if let Some(ms) = ms {
+                tokio::time::sleep(Duration::from_millis(*ms)).await;
+            }
+        }
+        Step::Click { selector } => {
+            page.find_element(selector.as_str())
+                .await
+                .map_err(|e| format!("Selector '{}' matched no element: {}", selector, e))?
+                .click()
+                .await
+                .map_err(|e| format!("Failed to click '{}': {}", selector, e))?;
+        }
+        Step::Fill { selector, value } => {
+            // the native setter so frameworks tracking the value notice the change
+            let filled: bool = evaluate(
+                page,
+                &format!(
+                    r#"(() => {{
+                        const element = document.querySelector({});
+                        if (!element) return false;
+                        element.focus();
+                        const prototype = Object.getPrototypeOf(element);
+                        const setter = Object.getOwnPropertyDescriptor(prototype, 'value');
+                        if (setter && setter.set) setter.set.call(element, {});
+                        else element.value = {1};
+                        element.dispatchEvent(new Event('input', {{ bubbles: true }}));
+                        element.dispatchEvent(new Event('change', {{ bubbles: true }}));
+                        return true;
+                    }})()"#,
+                    serde_json::to_string(selector).map_err(|e| e.to_string())?,
+                    serde_json::to_string(value).map_err(|e| e.to_string())?,
+                ),
+            )
+            .await?;
+
+            if !filled {
+                return Err(format!("Selector '{}' matched no element", selector));
+            }
+        }
+        Step::Eval { expression } => {
+            // undefined has no JSON value, report it as null
+            let wrapped = format!(
+                "(async () => {{ const value = await ({}); return value === undefined ? null : value; }})()",
+                expression
+            );
+            let value: serde_json::Value = evaluate(page, &wrapped).await?;
+            outcome.value = Some(value);
+        }
+        Step::Screenshot { label } => {
+            let label = label.clone().unwrap_or_else(|| format!("step-{:02}", outcome.step));
+            let safe_label: String = label
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+                .collect();
+            let file_path = suffixed_path(&args.output, &format!("-{}", safe_label));
+
+            let screenshot_data = capture_current(page, args, result).await?;
+            let encoded = postprocess::encode_side_capture(args, screenshot_data.clone())?;
+            std::fs::write(&file_path, &encoded)
+                .map_err(|e| format!("Failed to save screenshot '{}': {}", label, e))?;
+
+            outcome.label = Some(label);
+            outcome.file_path = Some(file_path);
+            outcome.size = Some(encoded.len());
+            return Ok(Some(screenshot_data));
+        }
+    }
+
+    Ok(None)
+}
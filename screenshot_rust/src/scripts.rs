@@ -0,0 +1,110 @@
+// Init scripts evaluated on every new document before the page's own scripts run.
+
+/// Seed `Math.random` and replace the clock with a fake one so time and randomness driven
+/// rendering (canvas, WebGL, animations) is identical across runs.
+///
+/// The fake clock starts at 2024-01-01T00:00:00Z and advances 16ms on every read.
+pub fn deterministic(seed: u32) -> String {
+    format!(
+        r#"(() => {{
+    let state = {seed} >>> 0;
+    Math.random = function random() {{
+        state = (state + 0x6d2b79f5) | 0;
+        let t = Math.imul(state ^ (state >>> 15), 1 | state);
+        t = (t + Math.imul(t ^ (t >>> 7), 61 | t)) ^ t;
+        return ((t ^ (t >>> 14)) >>> 0) / 4294967296;
+    }};
+
+    const epoch = 1704067200000;
+    let elapsed = 0;
+    const tick = () => (elapsed += 16);
+    const RealDate = Date;
+
+    function FakeDate(...args) {{
+        if (!new.target) return new RealDate(epoch + tick()).toString();
+        return args.length ? new RealDate(...args) : new RealDate(epoch + tick());
+    }}
+    FakeDate.prototype = RealDate.prototype;
+    FakeDate.now = () => epoch + tick();
+    FakeDate.parse = RealDate.parse;
+    FakeDate.UTC = RealDate.UTC;
+    window.Date = FakeDate;
+
+    performance.now = () => tick();
+}})();"#,
+        seed = seed
+    )
+}
+
+/// Set `window.name` and stand in a fake `window.opener` at `opener_url`, for pages that render
+/// differently when opened as a popup. Only the top-level document is changed.
+pub fn window_context(name: Option<&str>, opener_url: Option<&str>) -> String {
+    let json = |value: Option<&str>| serde_json::to_string(&value).unwrap_or_else(|_| "null".to_string());
+
+    format!(
+        r#"(() => {{
+    if (window !== window.top) return;
+
+    const name = {name};
+    if (name !== null) window.name = name;
+
+    const openerUrl = {opener};
+    if (openerUrl !== null) {{
+        const location = new URL(openerUrl);
+        const opener = {{
+            closed: false,
+            location: {{ href: location.href, origin: location.origin, toString: () => location.href }},
+            origin: location.origin,
+            postMessage: () => {{}},
+            focus: () => {{}},
+            close: () => {{}},
+        }};
+        opener.window = opener;
+        opener.self = opener;
+        Object.defineProperty(window, 'opener', {{ get: () => opener, set: () => {{}}, configurable: true }});
+    }}
+}})();"#,
+        name = json(name),
+        opener = json(opener_url)
+    )
+}
+
+/// Append `css` as a `<style>` element once the document has been parsed, so it comes after
+/// and overrides the page's own stylesheets.
+pub fn inject_style(css: &str) -> String {
+    format!(
+        r#"(() => {{
+    const add = () => {{
+        const style = document.createElement('style');
+        style.textContent = {css};
+        (document.head || document.documentElement).appendChild(style);
+    }};
+    if (document.readyState === 'loading') document.addEventListener('DOMContentLoaded', add);
+    else add();
+}})();"#,
+        css = serde_json::to_string(css).unwrap_or_else(|_| "''".to_string())
+    )
+}
+
+/// Give the document a `<meta name="viewport">` with `content`, replacing any the page declares.
+/// The page's own tags are rewritten as the parser adds them so a late tag cannot win.
+pub fn viewport_meta(content: &str) -> String {
+    format!(
+        r#"(() => {{
+    const content = {content};
+    const apply = () => {{
+        const tags = document.querySelectorAll('meta[name="viewport" i]');
+        tags.forEach(tag => {{ if (tag.content !== content) tag.content = content; }});
+        if (tags.length === 0 && document.head) {{
+            const meta = document.createElement('meta');
+            meta.name = 'viewport';
+            meta.content = content;
+            document.head.prepend(meta);
+        }}
+    }};
+    new MutationObserver(apply).observe(document, {{ childList: true, subtree: true, attributes: true, attributeFilter: ['content'] }});
+    document.addEventListener('DOMContentLoaded', apply);
+}})();"#,
+        content = serde_json::to_string(content).unwrap_or_else(|_| "''".to_string())
+    )
+}
@@ -0,0 +1,87 @@
+// Detached ed25519 signatures over a capture and its provenance, written to a `.sig` sidecar.
+
+use crate::metadata::Provenance;
+use base64::{engine::general_purpose, Engine as _};
+use ed25519_dalek::pkcs8::DecodePrivateKey;
+use ed25519_dalek::{Signer, SigningKey};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The signature of a capture, as reported in the result.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Signature {
+    pub file_path: String,
+    /// base64 of the 64 byte ed25519 signature.
+    pub signature: String,
+    /// `SHA256:` and the hex digest of the public key.
+    pub public_key_fingerprint: String,
+}
+
+/// What is signed: the image digest and where and how it was captured.
+#[derive(Serialize)]
+struct Payload<'a> {
+    image_sha256: String,
+    url: &'a str,
+    viewport: &'a str,
+    captured_at: u64,
+    software: &'a str,
+}
+
+/// The sidecar file. `payload` is kept as the exact signed string so it verifies byte for byte.
+#[derive(Serialize)]
+struct Sidecar<'a> {
+    algorithm: &'static str,
+    payload: &'a str,
+    signature: &'a str,
+    public_key: String,
+}
+
+/// Sign `image` with the key at `key_file` and write `<output>.sig` next to it.
+pub fn sign(image: &[u8], provenance: &Provenance, key_file: &str, output: &str) -> Result<Signature, String> {
+    let key = read_key(key_file)?;
+    let payload = serde_json::to_string(&Payload {
+        image_sha256: format!("{:x}", Sha256::digest(image)),
+        url: &provenance.url,
+        viewport: &provenance.viewport,
+        captured_at: provenance.captured_at,
+        software: &provenance.software,
+    })
+    .map_err(|e| e.to_string())?;
+
+    let signature = general_purpose::STANDARD.encode(key.sign(payload.as_bytes()).to_bytes());
+    let public_key = key.verifying_key().to_bytes();
+    let sidecar = Sidecar {
+        algorithm: "ed25519",
+        payload: &payload,
+        signature: &signature,
+        public_key: general_purpose::STANDARD.encode(public_key),
+    };
+
+    let file_path = format!("{}.sig", output);
+    let json = serde_json::to_string_pretty(&sidecar).map_err(|e| e.to_string())?;
+    std::fs::write(&file_path, json).map_err(|e| format!("Failed to write signature: {}", e))?;
+
+    Ok(Signature {
+        file_path,
+        signature,
+        public_key_fingerprint: format!("SHA256:{:x}", Sha256::digest(public_key)),
+    })
+}
+
+/// A PKCS#8 PEM key as written by `openssl genpkey -algorithm ed25519`, or a raw 32 byte seed.
+fn read_key(key_file: &str) -> Result<SigningKey, String> {
+    let bytes = std::fs::read(key_file).map_err(|e| format!("Failed to read signing key: {}", e))?;
+
+    if bytes.starts_with(b"-----BEGIN") {
+        let pem = String::from_utf8_lossy(&bytes);
+        return SigningKey::from_pkcs8_pem(&pem).map_err(|e| format!("Invalid signing key: {}", e));
+    }
+
+    let seed: [u8; 32] = bytes
+        .as_slice()
+        .try_into()
+        .map_err(|_| "Invalid signing key, expected a PKCS#8 PEM or a raw 32 byte ed25519 seed".to_string())?;
+
+    Ok(SigningKey::from_bytes(&seed))
+}
@@ -0,0 +1,82 @@
+// `--template-file`: frame the content of `--template-url` in a local HTML template, e.g. for
+// branded social cards.
+
+use crate::{evaluate, Args};
+use chromiumoxide::Page;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// How the content went into the template.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct TemplateInjection {
+    pub template: String,
+    pub placeholder: String,
+    pub injected: bool,
+    /// size of the framed document in CSS pixels.
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The body of the loaded page with every link and resource url made absolute, so it still
+/// resolves once moved into a template loaded from disk.
+const CONTENT: &str = r#"(() => {
+    const body = document.body.cloneNode(true);
+    body.querySelectorAll('script, noscript').forEach(element => element.remove());
+    for (const attribute of ['src', 'href', 'poster']) {
+        body.querySelectorAll(`[${attribute}]`).forEach(element => {
+            try {
+                element.setAttribute(attribute, new URL(element.getAttribute(attribute), document.baseURI).href);
+            } catch (e) {}
+        });
+    }
+    body.querySelectorAll('[srcset]').forEach(element => element.removeAttribute('srcset'));
+    return body.innerHTML;
+})()"#;
+
+/// Take the content of the page, load the template over it and put the content into the placeholder.
+pub async fn apply(page: &Page, args: &Args, template: &str) -> Result<TemplateInjection, String> {
+    let content: String = evaluate(page, CONTENT).await?;
+
+    let path = std::fs::canonicalize(template).map_err(|e| format!("Failed to read template '{}': {}", template, e))?;
+    let template_url = url::Url::from_file_path(&path)
+        .map_err(|_| format!("Template path '{}' cannot be turned into a file URL", path.display()))?;
+
+    page.goto(template_url.as_str())
+        .await
+        .map_err(|e| format!("Failed to load template '{}': {}", template, e))?;
+    page.wait_for_navigation()
+        .await
+        .map_err(|e| format!("Failed to wait for template '{}': {}", template, e))?;
+
+    let injected: bool = evaluate(
+        page,
+        &format!(
+            r#"(() => {{
+                const placeholder = document.querySelector({});
+                if (!placeholder) return false;
+                placeholder.innerHTML = {};
+                return true;
+            }})()"#,
+            serde_json::to_string(&args.template_placeholder).map_err(|e| e.to_string())?,
+            serde_json::to_string(&content).map_err(|e| e.to_string())?,
+        ),
+    )
+    .await?;
+
+    // let the injected images load
+    tokio::time::sleep(args.delay).await;
+
+    let (width, height): (u32, u32) = evaluate(
+        page,
+        "[document.documentElement.scrollWidth, document.documentElement.scrollHeight]",
+    )
+    .await?;
+
+    Ok(TemplateInjection {
+        template: template.to_string(),
+        placeholder: args.template_placeholder.clone(),
+        injected,
+        width,
+        height,
+    })
+}
@@ -0,0 +1,145 @@
+// `--max-capture-dimension`: full-page captures beyond Chrome's texture limits, taken in tiles
+// no larger than the limit and stitched back together.
+
+use crate::{applied_scale, capture_page, postprocess, Args, Rect, ScreenshotResult};
+use chromiumoxide::Page;
+use image::{ImageFormat, RgbaImage};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+/// How a capture too large for Chrome was taken instead.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct CaptureFallback {
+    pub page_width: u32,
+    pub page_height: u32,
+    pub limit: u32,
+    /// `tiled`, or `clipped` for output formats that cannot be stitched.
+    pub mode: String,
+    pub tiles: usize,
+    /// only part of the page was captured.
+    pub truncated: bool,
+}
+
+/// Capture the `width` x `height` CSS pixel page in tiles of at most `limit` output pixels a side
+/// at the device scale factor applied to the page, and stitch them.
+pub async fn capture(
+    page: &Page,
+    args: &Args,
+    width: u32,
+    height: u32,
+    limit: u32,
+    result: &mut ScreenshotResult,
+) -> Result<(Vec<u8>, CaptureFallback), String> {
+    let scale = applied_scale(result);
+    let format = match args.format.as_str() {
+        "jpeg" | "jpg" => ImageFormat::Jpeg,
+        // AVIF and lossless WebP are encoded from a PNG afterwards
+        "png" | "avif" => ImageFormat::Png,
+        "webp" if args.webp_lossless => ImageFormat::Png,
+        _ => return clip(page, args, width, height, limit, scale).await,
+    };
+
+    // tiles are lossless so seams do not show
+    let mut tile_args = args.clone();
+    tile_args.format = "png".to_string();
+
+    let (stitched_width, stitched_height) = (
+        (width as f64 * scale).round() as u32,
+        (height as f64 * scale).round() as u32,
+    );
+    postprocess::check_pixel_budget(args, stitched_width, stitched_height, result)?;
+
+    // fail before capturing any tile when the encoder cannot hold the stitched image
+    let max_side = match args.format.as_str() {
+        "jpeg" | "jpg" => Some(65535),
+        "webp" => Some(16383),
+        _ => None,
+    };
+    if let Some(max_side) = max_side.filter(|max_side| stitched_width.max(stitched_height) > *max_side) {
+        return Err(format!(
+            "A stitched {}x{} capture is too large for {} output ({} pixels at most per side)",
+            stitched_width, stitched_height, args.format, max_side
+        ));
+    }
+
+    let step = ((limit as f64 / scale).floor() as u32).max(1);
+    let mut stitched = RgbaImage::new(stitched_width, stitched_height);
+    let mut tiles = 0;
+
+    for y in (0..height).step_by(step as usize) {
+        for x in (0..width).step_by(step as usize) {
+            let tile = Rect {
+                x: x as f64,
+                y: y as f64,
+                width: step.min(width - x) as f64,
+                height: step.min(height - y) as f64,
+            };
+            let data = capture_page(page, &tile_args, Some(tile)).await?;
+            let image = image::load_from_memory(&data)
+                .map_err(|e| format!("Failed to decode tile: {}", e))?
+                .to_rgba8();
+
+            image::imageops::replace(
+                &mut stitched,
+                &image,
+                (x as f64 * scale).round() as i64,
+                (y as f64 * scale).round() as i64,
+            );
+            tiles += 1;
+        }
+    }
+
+    let mut encoded = Cursor::new(Vec::new());
+    if format == ImageFormat::Jpeg {
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut encoded, args.quality.clamp(1, 100))
+            .encode_image(&image::DynamicImage::ImageRgba8(stitched).to_rgb8())
+            .map_err(|e| format!("Failed to encode stitched capture: {}", e))?;
+    } else {
+        stitched
+            .write_to(&mut encoded, format)
+            .map_err(|e| format!("Failed to encode stitched capture: {}", e))?;
+    }
+
+    Ok((
+        encoded.into_inner(),
+        CaptureFallback {
+            page_width: width,
+            page_height: height,
+            limit,
+            mode: "tiled".to_string(),
+            tiles,
+            truncated: false,
+        },
+    ))
+}
+
+/// Capture the top left of the page that fits in `limit` output pixels a side.
+async fn clip(
+    page: &Page,
+    args: &Args,
+    width: u32,
+    height: u32,
+    limit: u32,
+    scale: f64,
+) -> Result<(Vec<u8>, CaptureFallback), String> {
+    let side = limit as f64 / scale;
+    let clip = Rect {
+        x: 0.0,
+        y: 0.0,
+        width: (width as f64).min(side).floor(),
+        height: (height as f64).min(side).floor(),
+    };
+
+    Ok((
+        capture_page(page, args, Some(clip)).await?,
+        CaptureFallback {
+            page_width: width,
+            page_height: height,
+            limit,
+            mode: "clipped".to_string(),
+            tiles: 1,
+            truncated: clip.width < width as f64 || clip.height < height as f64,
+        },
+    ))
+}
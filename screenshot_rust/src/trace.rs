@@ -0,0 +1,126 @@
+// `--trace`: record a Chrome performance trace around navigation and capture.
+
+use chromiumoxide::cdp::browser_protocol::tracing::{
+    EndParams, EventDataCollected, EventTracingComplete, StartParams, StartTransferMode, TraceConfig,
+};
+use chromiumoxide::Page;
+use futures::{FutureExt, StreamExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The categories the DevTools performance panel records.
+pub const DEFAULT_CATEGORIES: [&str; 10] = [
+    "devtools.timeline",
+    "disabled-by-default-devtools.timeline",
+    "disabled-by-default-devtools.timeline.frame",
+    "disabled-by-default-devtools.timeline.stack",
+    "disabled-by-default-v8.cpu_profiler",
+    "v8.execute",
+    "blink.console",
+    "blink.user_timing",
+    "latencyInfo",
+    "toplevel",
+];
+
+/// How long Chrome gets to flush the buffered trace once tracing ends.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A trace written next to the screenshot.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct Trace {
+    pub file_path: String,
+    pub categories: Vec<String>,
+    pub event_count: usize,
+}
+
+/// A trace being recorded on a page.
+pub struct Tracer {
+    categories: Vec<String>,
+    events: Arc<Mutex<Vec<serde_json::Value>>>,
+    complete: tokio::sync::oneshot::Receiver<()>,
+}
+
+/// Start tracing the page with `categories`.
+pub async fn start(page: &Page, categories: Vec<String>) -> Result<Tracer, String> {
+    let mut collected = page
+        .event_listener::<EventDataCollected>()
+        .await
+        .map_err(|e| format!("Failed to listen for trace data: {}", e))?;
+    let mut completed = page
+        .event_listener::<EventTracingComplete>()
+        .await
+        .map_err(|e| format!("Failed to listen for trace completion: {}", e))?;
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let task_events = events.clone();
+    let (done, complete) = tokio::sync::oneshot::channel();
+
+    tokio::spawn(async move {
+        let record = |event: Arc<EventDataCollected>| {
+            if let Ok(mut events) = task_events.lock() {
+                events.extend(event.value.iter().cloned());
+            }
+        };
+
+        loop {
+            tokio::select! {
+                // data first, Chrome sends every chunk before the completion
+                biased;
+                Some(event) = collected.next() => record(event),
+                Some(_) = completed.next() => {
+                    // chunks dispatched alongside the completion are already queued
+                    while let Some(Some(event)) = collected.next().now_or_never() {
+                        record(event);
+                    }
+                    let _ = done.send(());
+                    break;
+                }
+                else => break,
+            }
+        }
+    });
+
+    page.execute(
+        StartParams::builder()
+            .trace_config(TraceConfig::builder().included_categories(categories.clone()).build())
+            .transfer_mode(StartTransferMode::ReportEvents)
+            .build(),
+    )
+    .await
+    .map_err(|e| format!("Failed to start tracing: {}", e))?;
+
+    Ok(Tracer {
+        categories,
+        events,
+        complete,
+    })
+}
+
+impl Tracer {
+    /// End tracing and write the events to `path` in the Trace Event Format.
+    pub async fn stop(self, page: &Page, path: &str) -> Result<Trace, String> {
+        page.execute(EndParams::default())
+            .await
+            .map_err(|e| format!("Failed to stop tracing: {}", e))?;
+
+        tokio::time::timeout(FLUSH_TIMEOUT, self.complete)
+            .await
+            .map_err(|_| "Timed out waiting for the trace to complete".to_string())?
+            .map_err(|_| "Tracing ended without completing".to_string())?;
+
+        let events = std::mem::take(&mut *self.events.lock().map_err(|_| "Trace buffer is poisoned".to_string())?);
+        let event_count = events.len();
+        let trace = serde_json::to_vec(&serde_json::json!({ "traceEvents": events }))
+            .map_err(|e| format!("Failed to serialize trace: {}", e))?;
+
+        std::fs::write(path, trace).map_err(|e| format!("Failed to save trace: {}", e))?;
+
+        Ok(Trace {
+            file_path: path.to_string(),
+            categories: self.categories,
+            event_count,
+        })
+    }
+}
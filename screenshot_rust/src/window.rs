@@ -0,0 +1,90 @@
+use chromiumoxide::cdp::browser_protocol::browser::GetWindowForTargetParams;
+use chromiumoxide::cdp::browser_protocol::page::BringToFrontParams;
+use chromiumoxide::Page;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// The OS window captured with its browser chrome.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WindowCapture {
+    pub left: i64,
+    pub top: i64,
+    pub width: i64,
+    pub height: i64,
+    /// the platform tool used to grab the screen region.
+    pub tool: String,
+}
+
+/// Capture the whole OS window of the page (tabs and address bar included) as PNG.
+///
+/// CDP can only capture the page itself, so the window bounds are looked up over CDP and the
+/// screen region is grabbed with the platform screen capture tool.
+pub async fn capture_window(page: &Page) -> Result<(Vec<u8>, WindowCapture), String> {
+    let window = page
+        .execute(
+            GetWindowForTargetParams::builder()
+                .target_id(page.target_id().clone())
+                .build(),
+        )
+        .await
+        .map_err(|e| format!("Failed to get the browser window: {}", e))?;
+
+    page.execute(BringToFrontParams::default())
+        .await
+        .map_err(|e| format!("Failed to bring the window to front: {}", e))?;
+
+    let bounds = &window.result.bounds;
+    let capture = WindowCapture {
+        left: bounds.left.unwrap_or_default(),
+        top: bounds.top.unwrap_or_default(),
+        width: bounds.width.unwrap_or_default(),
+        height: bounds.height.unwrap_or_default(),
+        tool: String::new(),
+    };
+
+    // give the window manager a moment to raise the window
+    tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
+
+    let path = std::env::temp_dir().join(format!("screenshot-window-{}.png", std::process::id()));
+    let path_arg = path.to_string_lossy().into_owned();
+    let tool = grab_region(&capture, &path_arg)?;
+    let data = fs::read(&path).map_err(|e| format!("Failed to read window capture: {}", e));
+    let _ = fs::remove_file(&path);
+
+    Ok((data?, WindowCapture { tool, ..capture }))
+}
+
+#[cfg(target_os = "macos")]
+fn grab_region(capture: &WindowCapture, path: &str) -> Result<String, String> {
+    let region = format!("-R{},{},{},{}", capture.left, capture.top, capture.width, capture.height);
+    run_tool("screencapture", &["-x", &region, path])
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn grab_region(capture: &WindowCapture, path: &str) -> Result<String, String> {
+    // ImageMagick on X11, wayland compositors do not allow grabbing arbitrary regions
+    let crop = format!("{}x{}+{}+{}", capture.width, capture.height, capture.left, capture.top);
+    run_tool("import", &["-window", "root", "-crop", &crop, path])
+}
+
+#[cfg(not(unix))]
+fn grab_region(_capture: &WindowCapture, _path: &str) -> Result<String, String> {
+    Err("Capturing the browser window is not supported on this platform".to_string())
+}
+
+#[cfg(unix)]
+fn run_tool(tool: &str, args: &[&str]) -> Result<String, String> {
+    let status = std::process::Command::new(tool).args(args).status().map_err(|e| {
+        format!(
+            "Capturing the browser window is not supported here, '{}' is unavailable: {}",
+            tool, e
+        )
+    })?;
+
+    if !status.success() {
+        return Err(format!("'{}' failed to capture the browser window: {}", tool, status));
+    }
+
+    Ok(tool.to_string())
+}